@@ -1,12 +1,19 @@
 // Configuration management for Samwise
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
-use tauri::{AppHandle, Manager};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
 
-// The text backend to use. Both are local command-line tools.
+use crate::error::SamwiseError;
+use crate::llm_client::{self, Backend};
+use crate::menu;
+use crate::state::AppState;
+
+// The text backend to use: the first one in the registry.
 fn default_backend() -> String {
-    "claude".to_string()
+    llm_client::default_backend().id().to_string()
 }
 
 // Model passed to the Claude CLI's --model flag. "haiku" is the fast option,
@@ -16,9 +23,168 @@ fn default_claude_model() -> String {
     "haiku".to_string()
 }
 
+// Model for the Anthropic API backend. Same reasoning as the CLI: the fast
+// model is plenty for short edits.
+fn default_anthropic_model() -> String {
+    "claude-haiku-4-5".to_string()
+}
+
+// Where the Anthropic API lives. Override to point at a proxy or, in tests,
+// a local mock server.
+fn default_anthropic_base_url() -> String {
+    "https://api.anthropic.com".to_string()
+}
+
+// Where the OpenAI-compatible backend sends requests, including the version
+// path. Point it at LM Studio (http://localhost:1234/v1), vLLM, a proxy or an
+// internal gateway.
+fn default_openai_base_url() -> String {
+    "https://api.openai.com/v1".to_string()
+}
+
+fn default_openai_model() -> String {
+    "gpt-4o-mini".to_string()
+}
+
+// Where the local Ollama daemon listens.
+fn default_ollama_base_url() -> String {
+    "http://localhost:11434".to_string()
+}
+
+// How long a call may take before it is killed. Generous, since a big text on
+// a slow model can take a while, but a hung CLI (an auth prompt, a network
+// stall) no longer blocks forever.
+fn default_timeout_secs() -> u64 {
+    180
+}
+
+// Settings that apply to one backend, keyed by backend id in
+// `AppConfig.backend_options`. A backend without an entry uses the defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendOptions {
+    // Seconds before the call is given up on; 0 means no limit. For CLIs it
+    // is the whole run, for HTTP backends the longest wait for more data.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    // Extra command-line arguments, added after Samwise's own (e.g. an effort
+    // flag). Command-line backends only.
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    // Extra environment variables for the process (e.g. a config dir or
+    // HTTPS_PROXY). Command-line backends only.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    // Models for the picker, replacing the built-in list (e.g. to add a model
+    // newer than this build). Command-line backends only.
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+impl Default for BackendOptions {
+    fn default() -> Self {
+        BackendOptions {
+            timeout_secs: default_timeout_secs(),
+            extra_args: Vec::new(),
+            env: HashMap::new(),
+            models: Vec::new(),
+        }
+    }
+}
+
+impl BackendOptions {
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+}
+
+// How often a call that failed for a passing reason (rate limit, overloaded
+// server) is tried again, and how long to wait in between. The wait doubles
+// each time, starting at `base_delay_ms`, and is spread by up to `jitter`
+// (0.2 = ±20%) so parallel clients don't retry in lockstep.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    // Tries in total, the first one included; 1 turns retries off.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_base_delay_ms")]
+    pub base_delay_ms: u64,
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_base_delay_ms() -> u64 {
+    1000
+}
+
+fn default_jitter() -> f64 {
+    0.2
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: default_max_attempts(),
+            base_delay_ms: default_base_delay_ms(),
+            jitter: default_jitter(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // The wait before try number `attempt` (2 for the first retry).
+    // `random` is in [0, 1) and picks where in the jitter range it lands.
+    pub fn delay(&self, attempt: u32, random: f64) -> Duration {
+        let exponent = attempt.saturating_sub(2).min(16);
+        let base = self.base_delay_ms as f64 * f64::from(1u32 << exponent);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (2.0 * random - 1.0);
+        Duration::from_millis((base * factor).round() as u64)
+    }
+}
+
+// A backend defined by the user as a command template (see
+// `llm_client::CommandBackend` for the placeholders). For example:
+//   { "id": "llm", "name": "llm", "command": ["llm", "-m", "{model}", "{prompt}"] }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomBackend {
+    pub id: String,
+    // Name for the menu and Settings. Empty means "use the id".
+    #[serde(default)]
+    pub name: String,
+    // Program and arguments, with placeholders.
+    pub command: Vec<String>,
+    // Command that checks the tool is installed. Empty means
+    // `<program> --version`.
+    #[serde(default)]
+    pub version_command: Vec<String>,
+    // Model filled into {model}. Empty drops the model argument.
+    #[serde(default)]
+    pub model: String,
+    // Models to offer in the picker.
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+impl CustomBackend {
+    // The availability probe to run for this backend.
+    pub fn version_command(&self) -> Vec<String> {
+        if !self.version_command.is_empty() {
+            return self.version_command.clone();
+        }
+        match self.command.first() {
+            Some(program) => vec![program.clone(), "--version".to_string()],
+            None => Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
-    // Which CLI tool runs the text: "claude" or "codex".
+    // Which backend runs the text, by id (see `llm_client::backends()`).
     #[serde(default = "default_backend")]
     pub backend: String,
     // Model for the Claude CLI (e.g. "haiku", "sonnet", "opus").
@@ -27,6 +193,52 @@ pub struct AppConfig {
     // Model for the Codex CLI. Empty means "use Codex's own default".
     #[serde(default)]
     pub codex_model: String,
+    // API key for the Anthropic API backend. Empty means "use the
+    // ANTHROPIC_API_KEY environment variable".
+    #[serde(default)]
+    pub anthropic_api_key: String,
+    // Model for the Anthropic API backend (a full model id).
+    #[serde(default = "default_anthropic_model")]
+    pub anthropic_model: String,
+    // Base URL of the Anthropic API, without the /v1 path.
+    #[serde(default = "default_anthropic_base_url")]
+    pub anthropic_base_url: String,
+    // Base URL of the OpenAI-compatible backend, including /v1.
+    #[serde(default = "default_openai_base_url")]
+    pub openai_base_url: String,
+    // Model for the OpenAI-compatible backend. Empty lets single-model
+    // servers pick their own.
+    #[serde(default = "default_openai_model")]
+    pub openai_model: String,
+    // API key for the OpenAI-compatible backend. Empty means "use the
+    // OPENAI_API_KEY environment variable", or no key for local servers.
+    #[serde(default)]
+    pub openai_api_key: String,
+    // Base URL of the local Ollama daemon.
+    #[serde(default = "default_ollama_base_url")]
+    pub ollama_base_url: String,
+    // Ollama model tag (e.g. "llama3.2"). Empty means "the first installed
+    // model".
+    #[serde(default)]
+    pub ollama_model: String,
+    // Extra backends defined as command templates.
+    #[serde(default)]
+    pub custom_backends: Vec<CustomBackend>,
+    // Backends to try, in order, when the active one fails because it isn't
+    // installed or signed in, is rate limited or overloaded, or times out.
+    #[serde(default)]
+    pub fallback_backends: Vec<String>,
+    // Values for `{{name}}` placeholders in prompts, e.g. "language". Values
+    // given when applying a prompt win; date, clipboard and user_name have
+    // built-in values.
+    #[serde(default)]
+    pub prompt_variables: HashMap<String, String>,
+    // Retries for rate limits and overloaded servers, before any fallback.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    // Per-backend settings such as the timeout, by backend id.
+    #[serde(default)]
+    pub backend_options: HashMap<String, BackendOptions>,
     pub global_hotkey: String,
 }
 
@@ -36,6 +248,19 @@ impl Default for AppConfig {
             backend: default_backend(),
             claude_model: default_claude_model(),
             codex_model: String::new(),
+            anthropic_api_key: String::new(),
+            anthropic_model: default_anthropic_model(),
+            anthropic_base_url: default_anthropic_base_url(),
+            openai_base_url: default_openai_base_url(),
+            openai_model: default_openai_model(),
+            openai_api_key: String::new(),
+            ollama_base_url: default_ollama_base_url(),
+            ollama_model: String::new(),
+            custom_backends: Vec::new(),
+            fallback_backends: Vec::new(),
+            prompt_variables: HashMap::new(),
+            retry: RetryPolicy::default(),
+            backend_options: HashMap::new(),
             // Use Super+Alt+S as default - reliable and usually free on most systems
             global_hotkey: "Super+Alt+S".to_string(),
         }
@@ -58,7 +283,7 @@ impl AppConfig {
                     match serde_json::from_str::<AppConfig>(&contents) {
                         Ok(mut config) => {
                             // Migrate old default hotkeys to new default
-                            let old_defaults = vec![
+                            let old_defaults = [
                                 "CmdOrCtrl+Shift+Space",
                                 "Ctrl+Shift+Space",
                                 "Super+Space",
@@ -73,8 +298,8 @@ impl AppConfig {
                                 }
                             }
                             // Old configs may have a model name here. Map anything
-                            // that isn't "codex" back to the "claude" backend.
-                            if config.backend != "claude" && config.backend != "codex" {
+                            // that isn't a known backend back to the default.
+                            if llm_client::find_backend(&config, &config.backend).is_none() {
                                 config.backend = default_backend();
                             }
                             config
@@ -95,6 +320,43 @@ impl AppConfig {
         }
     }
 
+    // The backend picked in the config, or the default for unknown ids.
+    pub fn active_backend(&self) -> Box<dyn Backend> {
+        llm_client::find_backend(self, &self.backend).unwrap_or_else(llm_client::default_backend)
+    }
+
+    // The settings for one backend, or the defaults when it has no entry.
+    pub fn backend_options(&self, id: &str) -> BackendOptions {
+        self.backend_options.get(id).cloned().unwrap_or_default()
+    }
+
+    // Check the models that changed since `previous`, for backends that know
+    // their full model list, so a typo shows up in Settings instead of as a
+    // CLI error at apply time. Models that were already saved are left
+    // alone, so an old model name doesn't block saving something else.
+    pub fn validate(&self, previous: &AppConfig) -> Result<(), SamwiseError> {
+        for backend in llm_client::backends(self) {
+            let model = backend.model(self);
+            if !backend.checks_model() || model.is_empty() {
+                continue;
+            }
+            let unchanged = llm_client::find_backend(previous, backend.id()).is_some_and(|b| b.model(previous) == model);
+            if unchanged || backend.accepts_model(self, &model) {
+                continue;
+            }
+            return Err(SamwiseError::InvalidConfig {
+                message: format!(
+                    "'{}' isn't a {} model. Known models: {}. Newer models can be added under backend_options.{}.models.",
+                    model,
+                    backend.display_name(),
+                    backend.models(self).join(", "),
+                    backend.id()
+                ),
+            });
+        }
+        Ok(())
+    }
+
     pub fn save(&self, app: &AppHandle) -> Result<(), String> {
         let config_path = Self::config_path(app);
         let json = serde_json::to_string_pretty(self)
@@ -108,30 +370,161 @@ impl AppConfig {
 }
 
 #[tauri::command]
-pub fn get_config(app: AppHandle) -> AppConfig {
-    AppConfig::load(&app)
+pub fn get_config(state: State<'_, AppState>) -> AppConfig {
+    state.config()
 }
 
 #[tauri::command]
-pub fn save_config(app: AppHandle, config: AppConfig) -> Result<(), String> {
-    config.save(&app)?;
+pub fn save_config(app: AppHandle, state: State<'_, AppState>, config: AppConfig) -> Result<(), String> {
+    config.validate(&state.config()).map_err(|e| e.to_string())?;
+    state.save_config(&app, config)?;
+    menu::update_backend_menu(&app);
     Ok(())
 }
 
+// What the UI needs to know about one backend.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendInfo {
+    pub id: String,
+    pub name: String,
+    pub available: bool,
+    pub models: Vec<String>,
+}
+
+// The availability probes start CLIs and make HTTP calls, so they run off
+// the async runtime (and off the main thread, which would freeze the window).
 #[tauri::command]
-pub fn check_claude_cli() -> bool {
-    cli_available("claude")
+pub async fn list_backends(state: State<'_, AppState>) -> Result<Vec<BackendInfo>, String> {
+    let config = state.config();
+    tokio::task::spawn_blocking(move || {
+        llm_client::backends(&config)
+            .iter()
+            .map(|b| BackendInfo {
+                id: b.id().to_string(),
+                name: b.display_name().to_string(),
+                available: b.is_available(&config),
+                models: b.models(&config),
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Listing backends failed: {}", e))
 }
 
+// The models a backend offers right now: HTTP backends ask their models
+// endpoint, Ollama lists the installed tags, CLIs use their curated list (or
+// `backend_options.<id>.models`). The backend's model submenu shows what was
+// found.
 #[tauri::command]
-pub fn check_codex_cli() -> bool {
-    cli_available("codex")
+pub async fn list_models(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    backend: String,
+) -> Result<Vec<String>, SamwiseError> {
+    let config = state.config();
+    let backend = llm_client::find_backend(&config, &backend).ok_or_else(|| SamwiseError::InvalidConfig {
+        message: format!("Unknown backend '{}'", backend),
+    })?;
+    // The HTTP calls block, so keep them (and the menu update) off the async
+    // runtime.
+    tokio::task::spawn_blocking(move || {
+        let models = backend.list_models(&config)?;
+        menu::set_discovered_models(&app, backend.id(), models.clone());
+        Ok(models)
+    })
+    .await
+    .map_err(|e| SamwiseError::InvalidConfig {
+        message: format!("Listing models failed: {}", e),
+    })?
 }
 
-// True if the given command exists and runs `--version` without error.
-fn cli_available(command: &str) -> bool {
-    std::process::Command::new(command)
-        .arg("--version")
-        .output()
-        .is_ok()
+// Generic availability probe: CLI backends run their version command
+// (`claude --version`, or a custom backend's `version_command`), HTTP
+// backends check their key or server.
+// Runs off the main thread, like `list_backends`.
+#[tauri::command]
+pub async fn check_backend(state: State<'_, AppState>, backend: String) -> Result<bool, String> {
+    let config = state.config();
+    let Some(backend) = llm_client::find_backend(&config, &backend) else {
+        return Ok(false);
+    };
+    tokio::task::spawn_blocking(move || backend.is_available(&config))
+        .await
+        .map_err(|e| format!("Checking the backend failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_rejects_unknown_cli_models() {
+        let config = AppConfig {
+            claude_model: "sonet".to_string(),
+            ..AppConfig::default()
+        };
+        let err = config.validate(&AppConfig::default()).unwrap_err();
+        assert!(
+            matches!(&err, SamwiseError::InvalidConfig { message } if message.contains("'sonet'")),
+            "{:?}",
+            err
+        );
+
+        assert!(AppConfig::default().validate(&AppConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn validate_only_checks_models_that_changed() {
+        let saved = AppConfig {
+            codex_model: "gpt-4-codex-old".to_string(),
+            ..AppConfig::default()
+        };
+        let new_hotkey = AppConfig {
+            global_hotkey: "CmdOrCtrl+Shift+K".to_string(),
+            ..saved.clone()
+        };
+        assert!(new_hotkey.validate(&saved).is_ok());
+
+        let new_model = AppConfig {
+            codex_model: "gpt-4-codex-typo".to_string(),
+            ..saved.clone()
+        };
+        assert!(new_model.validate(&saved).is_err());
+    }
+
+    #[test]
+    fn validate_accepts_full_claude_model_ids() {
+        let config = AppConfig {
+            claude_model: "claude-sonnet-4-20250514".to_string(),
+            ..AppConfig::default()
+        };
+        assert!(config.validate(&AppConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn validate_accepts_models_added_in_backend_options() {
+        let config = AppConfig {
+            codex_model: "gpt-6-codex".to_string(),
+            backend_options: [(
+                "codex".to_string(),
+                BackendOptions {
+                    models: vec!["gpt-6-codex".to_string()],
+                    ..BackendOptions::default()
+                },
+            )]
+            .into(),
+            ..AppConfig::default()
+        };
+        assert!(config.validate(&AppConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn validate_leaves_free_text_backends_alone() {
+        let config = AppConfig {
+            openai_model: "anything-goes".to_string(),
+            anthropic_model: "claude-3-5-haiku-latest".to_string(),
+            ..AppConfig::default()
+        };
+        assert!(config.validate(&AppConfig::default()).is_ok());
+    }
 }
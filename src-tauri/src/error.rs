@@ -0,0 +1,302 @@
+// Errors returned to the frontend.
+//
+// Serialized with a `kind` tag (e.g. `{ "kind": "RateLimited", ... }`) so the
+// UI can show the right fix for each case instead of one generic message.
+// `Display` gives the same information as a sentence, for logs and for
+// frontends that just print the error.
+
+use std::fmt;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind")]
+pub enum SamwiseError {
+    // The CLI can't be started, or the local server isn't running.
+    NotInstalled { backend: String, hint: String },
+    // Not signed in, or the API key is missing or was rejected.
+    NotAuthenticated { backend: String, message: String },
+    RateLimited { backend: String, message: String },
+    // The service is up but can't take the request right now: an HTTP 5xx,
+    // or a CLI saying it is overloaded.
+    Overloaded { backend: String, message: String },
+    // Killed after running past its backend's timeout.
+    Timeout { backend: String, secs: u64 },
+    PromptNotFound { id: String },
+    // The prompt has a `{{name}}` placeholder that nothing gave a value for.
+    MissingVariable { prompt: String, name: String },
+    InvalidConfig { message: String },
+    // Step `step` (1-based) of the `pipeline` prompt, the `name` prompt,
+    // failed with `error`. The earlier steps' output is dropped.
+    StepFailed {
+        pipeline: String,
+        step: usize,
+        steps: usize,
+        name: String,
+        error: Box<SamwiseError>,
+    },
+    // Anything else; `stderr` holds what the backend said.
+    BackendFailed { backend: String, stderr: String },
+    // Stopped through `cancel_job`.
+    Cancelled,
+}
+
+impl SamwiseError {
+    pub fn failed(backend: &str, stderr: impl Into<String>) -> Self {
+        SamwiseError::BackendFailed {
+            backend: backend.to_string(),
+            stderr: stderr.into(),
+        }
+    }
+
+    // Failures another backend may well not have: this one isn't set up,
+    // isn't signed in, is out of quota or hung. These move a request on to
+    // the next fallback backend; anything else is returned as-is.
+    pub fn should_fall_back(&self) -> bool {
+        matches!(
+            self,
+            SamwiseError::NotInstalled { .. }
+                | SamwiseError::NotAuthenticated { .. }
+                | SamwiseError::RateLimited { .. }
+                | SamwiseError::Overloaded { .. }
+                | SamwiseError::Timeout { .. }
+        )
+    }
+
+    // Failures that tend to go away on their own, so the same backend is
+    // worth another try after a pause (see `RetryPolicy`).
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, SamwiseError::RateLimited { .. } | SamwiseError::Overloaded { .. })
+    }
+
+    // Sort a backend's failure output (CLI stderr, or an error message sent
+    // by an API) into the variant that matches its fix. Unknown output stays
+    // `BackendFailed` with the text as-is. Phrases only count as whole words,
+    // and status codes only right after "error", "status", "code" or "http",
+    // so "tokens used: 4290" or a sentence about authentication isn't taken
+    // for a failure.
+    pub fn classify(backend: &str, output: &str) -> Self {
+        let text = output.trim();
+        let lower = text.to_lowercase();
+        let has = |phrases: &[&str]| phrases.iter().any(|p| contains_phrase(&lower, p));
+        let status = |codes: &[&str]| codes.iter().any(|c| contains_status(&lower, c));
+
+        if has(&["rate limit", "rate limited", "rate_limit", "too many requests", "usage limit"]) || status(&["429"]) {
+            SamwiseError::RateLimited {
+                backend: backend.to_string(),
+                message: text.to_string(),
+            }
+        } else if has(&["overloaded", "service unavailable", "server is busy"]) || status(&["503", "529"]) {
+            SamwiseError::Overloaded {
+                backend: backend.to_string(),
+                message: text.to_string(),
+            }
+        } else if has(&[
+            "not logged in",
+            "/login",
+            "please log in",
+            "invalid api key",
+            "invalid x-api-key",
+            "invalid_api_key",
+            "authentication_error",
+            "authentication failed",
+        ]) || status(&["401", "403"])
+        {
+            SamwiseError::NotAuthenticated {
+                backend: backend.to_string(),
+                message: text.to_string(),
+            }
+        } else {
+            SamwiseError::failed(backend, text)
+        }
+    }
+
+    // An HTTP error reply. The status decides when it is clear; otherwise
+    // the server's message is classified like CLI output. Any 5xx is the
+    // server's own trouble, so it counts as overloaded.
+    pub fn from_status(backend: &str, status: u16, message: &str) -> Self {
+        match status {
+            401 | 403 => SamwiseError::NotAuthenticated {
+                backend: backend.to_string(),
+                message: message.to_string(),
+            },
+            429 => SamwiseError::RateLimited {
+                backend: backend.to_string(),
+                message: message.to_string(),
+            },
+            500..=599 => SamwiseError::Overloaded {
+                backend: backend.to_string(),
+                message: format!("{} ({})", message, status),
+            },
+            _ => match SamwiseError::classify(backend, message) {
+                SamwiseError::BackendFailed { backend, stderr } => SamwiseError::BackendFailed {
+                    backend,
+                    stderr: format!("{} ({})", stderr, status),
+                },
+                other => other,
+            },
+        }
+    }
+}
+
+// Whether `phrase` occurs in `text` with no letter or digit right before or
+// after it.
+fn contains_phrase(text: &str, phrase: &str) -> bool {
+    phrase_starts(text, phrase).next().is_some()
+}
+
+// Whether status `code` occurs in `text` as a number of its own, right after
+// "error", "status", "code" or "http" (e.g. "API Error: 429", "HTTP/1.1 503",
+// "status=401").
+fn contains_status(text: &str, code: &str) -> bool {
+    phrase_starts(text, code).any(|start| {
+        let before = text[..start].trim_end_matches([' ', ':', '=', '"', '(', '[']);
+        ["error", "status", "code", "http", "http/1.1", "http/2"]
+            .iter()
+            .any(|word| before.ends_with(word))
+    })
+}
+
+// Where `phrase` starts in `text` as a whole word.
+fn phrase_starts<'a>(text: &'a str, phrase: &'a str) -> impl Iterator<Item = usize> + 'a {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+    text.match_indices(phrase).map(|(start, _)| start).filter(move |&start| {
+        !is_word(text[..start].chars().next_back()) && !is_word(text[start + phrase.len()..].chars().next())
+    })
+}
+
+impl fmt::Display for SamwiseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SamwiseError::NotInstalled { backend, hint } => {
+                write!(f, "{} isn't available. {}", backend, hint)
+            }
+            SamwiseError::NotAuthenticated { backend, message } => {
+                write!(f, "{} isn't signed in or the API key was rejected: {}", backend, message)
+            }
+            SamwiseError::RateLimited { backend, message } => {
+                write!(f, "{} is rate limited: {}. Wait a moment and try again.", backend, message)
+            }
+            SamwiseError::Overloaded { backend, message } => {
+                write!(f, "{} is overloaded: {}. Try again in a moment.", backend, message)
+            }
+            SamwiseError::Timeout { backend, secs } => write!(
+                f,
+                "{} timed out after {}s. Try again, or raise the timeout for this backend in Settings.",
+                backend, secs
+            ),
+            SamwiseError::PromptNotFound { id } => write!(f, "Prompt '{}' not found", id),
+            SamwiseError::MissingVariable { prompt, name } => write!(
+                f,
+                "Prompt '{}' needs a value for {{{{{}}}}}. Fill it in under Variables, or set it in prompt_variables in config.json.",
+                prompt, name
+            ),
+            SamwiseError::InvalidConfig { message } => write!(f, "Invalid config: {}", message),
+            SamwiseError::StepFailed {
+                pipeline,
+                step,
+                steps,
+                name,
+                error,
+            } => write!(f, "Step {} of {} in '{}' ({}) failed: {}", step, steps, pipeline, name, error),
+            SamwiseError::BackendFailed { backend, stderr } => write!(f, "{} failed: {}", backend, stderr),
+            SamwiseError::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for SamwiseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_with_kind_tag() {
+        let err = SamwiseError::Timeout {
+            backend: "Claude CLI".to_string(),
+            secs: 30,
+        };
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({ "kind": "Timeout", "backend": "Claude CLI", "secs": 30 })
+        );
+        assert_eq!(
+            serde_json::to_value(SamwiseError::Cancelled).unwrap(),
+            serde_json::json!({ "kind": "Cancelled" })
+        );
+        let step = SamwiseError::StepFailed {
+            pipeline: "polish".to_string(),
+            step: 2,
+            steps: 3,
+            name: "Make Concise".to_string(),
+            error: Box::new(SamwiseError::Cancelled),
+        };
+        assert_eq!(
+            serde_json::to_value(&step).unwrap(),
+            serde_json::json!({
+                "kind": "StepFailed",
+                "pipeline": "polish",
+                "step": 2,
+                "steps": 3,
+                "name": "Make Concise",
+                "error": { "kind": "Cancelled" },
+            })
+        );
+    }
+
+    #[test]
+    fn classifies_cli_stderr() {
+        let kind = |stderr: &str| match SamwiseError::classify("Claude CLI", stderr) {
+            SamwiseError::RateLimited { .. } => "rate",
+            SamwiseError::NotAuthenticated { .. } => "auth",
+            SamwiseError::Overloaded { .. } => "overloaded",
+            SamwiseError::BackendFailed { .. } => "failed",
+            _ => "other",
+        };
+        assert_eq!(kind("Invalid API key · Please run /login"), "auth");
+        assert_eq!(kind("Error: not logged in"), "auth");
+        assert_eq!(kind("API Error: 429 rate_limit_error"), "rate");
+        assert_eq!(kind("Claude usage limit reached|1760000000"), "rate");
+        assert_eq!(kind(r#"API Error: 529 {"type":"overloaded_error"}"#), "overloaded");
+        assert_eq!(kind("segmentation fault"), "failed");
+        assert_eq!(kind("HTTP/1.1 503 Service Unavailable"), "overloaded");
+        assert_eq!(kind("request failed with status: 401"), "auth");
+    }
+
+    #[test]
+    fn classify_ignores_numbers_and_words_that_only_look_like_errors() {
+        let kind = |stderr: &str| SamwiseError::classify("Codex CLI", stderr);
+        for stderr in [
+            "tokens used: 4290",
+            "processed 429 lines, then crashed",
+            "Error: no such file 12401.txt",
+            "Rewrite the paragraph about authentication and unauthorized access",
+        ] {
+            assert_eq!(kind(stderr), SamwiseError::failed("Codex CLI", stderr), "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn http_status_wins_over_message() {
+        assert!(matches!(
+            SamwiseError::from_status("OpenAI-compatible API", 401, "bad key"),
+            SamwiseError::NotAuthenticated { .. }
+        ));
+        assert!(matches!(
+            SamwiseError::from_status("OpenAI-compatible API", 429, "slow down"),
+            SamwiseError::RateLimited { .. }
+        ));
+        assert_eq!(
+            SamwiseError::from_status("Ollama", 500, "model crashed"),
+            SamwiseError::Overloaded {
+                backend: "Ollama".to_string(),
+                message: "model crashed (500)".to_string(),
+            }
+        );
+        assert_eq!(
+            SamwiseError::from_status("Ollama", 404, "model not found"),
+            SamwiseError::failed("Ollama", "model not found (404)")
+        );
+    }
+}
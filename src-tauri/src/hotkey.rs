@@ -3,6 +3,7 @@ use tauri::{AppHandle, Manager, Emitter};
 use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
 use tauri_plugin_clipboard_manager::ClipboardExt;
 use crate::config::AppConfig;
+use crate::state::AppState;
 
 pub fn setup_global_shortcut(app: &AppHandle, hotkey: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("Registering global shortcut: {}", hotkey);
@@ -67,10 +68,11 @@ pub fn update_global_shortcut(app: AppHandle, new_hotkey: String) -> Result<(),
         .map_err(|e| format!("Failed to register new shortcut: {}", e))?;
 
     // Update config
-    let mut config = AppConfig::load(&app);
-    config.global_hotkey = new_hotkey;
-    config.save(&app)?;
-
-    Ok(())
+    let state = app.state::<AppState>();
+    let config = AppConfig {
+        global_hotkey: new_hotkey,
+        ..state.config()
+    };
+    state.save_config(&app, config)
 }
 
@@ -0,0 +1,101 @@
+// Running apply_prompt calls, by job id, so the frontend can cancel one.
+//
+// A job remembers the process it is waiting on (if any). Cancelling kills
+// that process and everything it started, so a cancelled CLI call stops
+// right away instead of running on in the background and costing tokens.
+// HTTP backends check the flag between streamed lines.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tauri::State;
+
+#[derive(Default)]
+pub struct Job {
+    cancelled: AtomicBool,
+    pid: Mutex<Option<u32>>, // Process group leader of the running CLI
+}
+
+impl Job {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Mark the job cancelled and kill its process, if one is running.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        let pid = self.pid.lock().unwrap();
+        if let Some(pid) = *pid {
+            kill_process_tree(pid);
+        }
+    }
+
+    // Remember the process this job now waits on. If the job was cancelled
+    // before the process started, kill it straight away.
+    pub fn attach(&self, pid: u32) {
+        let mut current = self.pid.lock().unwrap();
+        *current = Some(pid);
+        if self.is_cancelled() {
+            kill_process_tree(pid);
+        }
+    }
+
+    // Forget the process once it has been waited on, so a cancel that comes
+    // later can't hit a reused pid.
+    pub fn detach(&self) {
+        *self.pid.lock().unwrap() = None;
+    }
+}
+
+// Jobs in flight, kept in Tauri state.
+#[derive(Default)]
+pub struct JobRegistry(Mutex<HashMap<String, Arc<Job>>>);
+
+impl JobRegistry {
+    pub fn start(&self, job_id: &str) -> Arc<Job> {
+        let job = Arc::new(Job::default());
+        self.0.lock().unwrap().insert(job_id.to_string(), job.clone());
+        job
+    }
+
+    pub fn finish(&self, job_id: &str) {
+        self.0.lock().unwrap().remove(job_id);
+    }
+
+    // Returns false when no such job is running (e.g. it already finished).
+    pub fn cancel(&self, job_id: &str) -> bool {
+        let job = self.0.lock().unwrap().get(job_id).cloned();
+        match job {
+            Some(job) => {
+                job.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[tauri::command]
+pub fn cancel_job(job_id: String, jobs: State<'_, JobRegistry>) -> bool {
+    println!("Cancel requested for job {}", job_id);
+    jobs.cancel(&job_id)
+}
+
+// CLIs are started in their own process group (see `run_in_dir`), so killing
+// the group also stops any helper processes they spawned.
+#[cfg(unix)]
+pub fn kill_process_tree(pid: u32) {
+    // SAFETY: kill(2) has no memory-safety requirements; a negative pid
+    // addresses the whole process group.
+    unsafe {
+        libc::kill(-(pid as i32), libc::SIGKILL);
+    }
+}
+
+#[cfg(windows)]
+pub fn kill_process_tree(pid: u32) {
+    let _ = std::process::Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .output();
+}
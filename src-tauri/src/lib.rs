@@ -2,87 +2,376 @@
 mod prompts;
 mod menu;
 mod config;
+mod error;
 mod llm_client;
 mod hotkey;
+mod jobs;
+mod state;
+mod watcher;
 
-use prompts::Prompt;
+use prompts::{Prompt, PromptGroup, PromptsReport, BUILTIN_VARIABLES};
+use error::SamwiseError;
 use config::AppConfig;
-use llm_client::LLMClient;
-use tauri::{AppHandle, Manager};
+use jobs::JobRegistry;
+use state::AppState;
+use llm_client::{ApplyResult, LLMClient, LLMRequest, Progress};
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
 
+// All prompts in display order (pinned, "raw", then by `order`), hidden ones
+// included.
 #[tauri::command]
-fn get_prompts() -> Vec<Prompt> {
-    Prompt::get_all_prompts()
+fn get_prompts(state: State<'_, AppState>) -> Vec<Prompt> {
+    state.prompts()
 }
 
+// The visible prompts in groups (pinned, then by category), as the main
+// window and the Prompts menu list them.
 #[tauri::command]
-async fn apply_prompt(prompt_id: String, text: String, app: AppHandle) -> Result<String, String> {
+fn get_prompt_groups(state: State<'_, AppState>) -> Vec<PromptGroup> {
+    Prompt::groups(&state.prompts())
+}
+
+// Read prompts.yaml from disk again and return the new list. On a read or
+// parse error the current prompts stay in use.
+#[tauri::command]
+fn reload_prompts(state: State<'_, AppState>) -> Result<Vec<Prompt>, String> {
+    state.reload_prompts()
+}
+
+// Editing prompts from the app. Each command changes the user's
+// prompts.yaml (checked first, with the old file kept as prompts.yaml.bak)
+// and returns the new list; nothing changes if it fails. The Prompts menu
+// follows along.
+#[tauri::command]
+fn create_prompt(prompt: Prompt, app: AppHandle, state: State<'_, AppState>) -> Result<Vec<Prompt>, String> {
+    state.create_prompt(prompt).inspect(|prompts| menu::update_prompts_menu(&app, prompts))
+}
+
+// `id` is the prompt's id before the change; `prompt` may rename it.
+#[tauri::command]
+fn update_prompt(
+    id: String,
+    prompt: Prompt,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<Prompt>, String> {
+    state.update_prompt(&id, prompt).inspect(|prompts| menu::update_prompts_menu(&app, prompts))
+}
+
+#[tauri::command]
+fn delete_prompt(id: String, app: AppHandle, state: State<'_, AppState>) -> Result<Vec<Prompt>, String> {
+    state.delete_prompt(&id).inspect(|prompts| menu::update_prompts_menu(&app, prompts))
+}
+
+// `ids` holds every prompt id, in the new order.
+#[tauri::command]
+fn reorder_prompts(ids: Vec<String>, app: AppHandle, state: State<'_, AppState>) -> Result<Vec<Prompt>, String> {
+    state.reorder_prompts(&ids).inspect(|prompts| menu::update_prompts_menu(&app, prompts))
+}
+
+// The `{{placeholders}}` used across all prompts that need a value from the
+// user (the built-in ones fill themselves in), for the Variables fields.
+#[tauri::command]
+fn get_prompt_variables(state: State<'_, AppState>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in state.prompts().iter().flat_map(Prompt::variables) {
+        if !BUILTIN_VARIABLES.contains(&name.as_str()) && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// Check prompts.yaml and list its problems, with line and column for YAML
+// errors. The UI runs this at startup and after every reload.
+#[tauri::command]
+fn validate_prompts(state: State<'_, AppState>) -> PromptsReport {
+    Prompt::check_file(&backend_ids(&state.config()))
+}
+
+// Ids of the backends in use (built-in and custom), for checking prompts.
+fn backend_ids(config: &AppConfig) -> Vec<String> {
+    llm_client::backends(config).iter().map(|b| b.id().to_string()).collect()
+}
+
+// Payload of the "apply-chunk" event: a piece of output as it streams in.
+#[derive(Clone, Serialize)]
+struct ApplyChunk {
+    job_id: String,
+    text: String,
+}
+
+// Payload of the "apply-progress" event: a status line for the UI. With
+// `restart` set, the output streamed so far is void (a fallback backend is
+// starting over).
+#[derive(Clone, Serialize)]
+struct ApplyProgress {
+    job_id: String,
+    message: String,
+    restart: bool,
+}
+
+// Payload of the "apply-done" event, sent once the job has finished.
+#[derive(Clone, Serialize)]
+struct ApplyDone {
+    job_id: String,
+    success: bool,
+}
+
+// Value of a built-in `{{placeholder}}`, if `name` is one.
+fn builtin_variable(app: &AppHandle, name: &str) -> Option<String> {
+    match name {
+        "date" => Some(chrono::Local::now().format("%Y-%m-%d").to_string()),
+        "clipboard" => app.clipboard().read_text().ok(),
+        "user_name" => std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok(),
+        _ => None,
+    }
+}
+
+// One step of applying a prompt: the request to send and where to send it.
+// The text is filled in when the step runs.
+struct StepRun {
+    name: String,
+    backend_id: String,
+    backend_name: String,
+    request: LLMRequest,
+}
+
+// Set up `prompt` to run: its backend (its own or the active one), model and
+// settings, and its system prompt with the variables filled in.
+fn step_run(
+    prompt: &Prompt,
+    config: &AppConfig,
+    variables: &HashMap<String, String>,
+    app: &AppHandle,
+) -> Result<StepRun, SamwiseError> {
+    // A prompt may pick its own backend and model. A model only makes sense
+    // for the backend it was picked for, so it needs the backend too.
+    let backend = match (&prompt.backend, &prompt.model) {
+        (Some(id), _) => llm_client::find_backend(config, id).ok_or_else(|| SamwiseError::InvalidConfig {
+            message: format!("Prompt '{}' asks for backend '{}', which doesn't exist", prompt.id, id),
+        })?,
+        (None, Some(model)) => {
+            return Err(SamwiseError::InvalidConfig {
+                message: format!(
+                    "Prompt '{}' sets model '{}' but no backend; add `backend:` to say which backend runs it",
+                    prompt.id, model
+                ),
+            })
+        }
+        (None, None) => config.active_backend(),
+    };
+    println!("Backend for {}: {}", prompt.id, backend.id());
+
+    // For "raw" prompt, use empty system prompt to send text directly to LLM
+    let system_prompt = if prompt.id == "raw" {
+        "".to_string()
+    } else {
+        prompt
+            .fill_variables(|name| {
+                variables
+                    .get(name)
+                    .or_else(|| config.prompt_variables.get(name))
+                    .filter(|value| !value.is_empty())
+                    .cloned()
+                    .or_else(|| builtin_variable(app, name))
+            })
+            .map_err(|name| SamwiseError::MissingVariable {
+                prompt: prompt.id.clone(),
+                name,
+            })?
+    };
+    Ok(StepRun {
+        name: prompt.name.clone(),
+        backend_id: backend.id().to_string(),
+        backend_name: backend.display_name().to_string(),
+        request: LLMRequest {
+            system_prompt,
+            user_content: String::new(),
+            model: prompt.model.clone().unwrap_or_else(|| backend.model(config)),
+            temperature: prompt.temperature,
+            max_tokens: prompt.max_tokens,
+            timeout_secs: prompt.timeout_secs,
+        },
+    })
+}
+
+// `job_id` is picked by the frontend so it can match the "apply-chunk" and
+// "apply-done" events to this call. The result holds the final, cleaned
+// text, which replaces whatever was streamed, plus usage, cost and timing.
+// The same id is passed to `cancel_job` to stop the call. Failures come back
+// as a tagged `SamwiseError` so the UI can show the matching fix.
+// `variables` fills the prompt's `{{placeholders}}`, ahead of
+// `prompt_variables` in the config and the built-ins. A pipeline prompt runs
+// its steps in turn, with an "apply-progress" event as each starts; if one
+// fails, the error says which.
+#[tauri::command]
+async fn apply_prompt(
+    prompt_id: String,
+    text: String,
+    job_id: String,
+    variables: Option<HashMap<String, String>>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+    jobs: State<'_, JobRegistry>,
+) -> Result<ApplyResult, SamwiseError> {
     println!("=== Apply Prompt Debug ===");
     println!("Prompt ID: {}", prompt_id);
+    println!("Job ID: {}", job_id);
     println!("Text length: {} chars", text.len());
     println!("Text preview: {}", &text.chars().take(100).collect::<String>());
 
     // Find the prompt
-    let prompts = Prompt::get_all_prompts();
+    let prompts = state.prompts();
     let prompt = prompts
         .iter()
         .find(|p| p.id == prompt_id)
-        .ok_or_else(|| "Prompt not found".to_string())?;
+        .ok_or_else(|| SamwiseError::PromptNotFound { id: prompt_id.clone() })?;
 
     println!("Found prompt: {}", prompt.name);
 
-    // Load configuration
-    let config = AppConfig::load(&app);
-    println!("Backend: {}", config.backend);
+    // A pipeline runs its steps one after another; any other prompt is a
+    // single step.
+    let steps = prompt.resolve_steps(&prompts).map_err(|message| SamwiseError::InvalidConfig {
+        message: format!("Prompt '{}': {}", prompt.id, message),
+    })?;
+
+    // Every step is set up before the first one runs, so a missing variable
+    // or unknown backend fails right away.
+    let config = state.config();
+    let variables = variables.unwrap_or_default();
+    let runs = steps
+        .iter()
+        .map(|step| step_run(step, &config, &variables, &app))
+        .collect::<Result<Vec<_>, _>>()?;
+    let pipeline = (runs.len() > 1).then(|| prompt.id.clone());
+    let backend_name = runs[0].backend_name.clone();
 
     // Create LLM client
     let client = LLMClient::new();
 
-    // Process the text with the selected model asynchronously
-    // For "raw" prompt, use empty system prompt to send text directly to LLM
-    let prompt_text = if prompt.id == "raw" {
-        "".to_string()
-    } else {
-        prompt.system_prompt.clone()
-    };
-    let text_clone = text.clone();
-    let backend = config.backend.clone();
-    // Pick the model for the chosen backend.
-    let model = if backend == "codex" {
-        config.codex_model.clone()
-    } else {
-        config.claude_model.clone()
-    };
-
+    let app_for_chunks = app.clone();
+    let job_id_for_chunks = job_id.clone();
+    let job = jobs.start(&job_id);
+    let job_for_call = job.clone();
     let result = tokio::task::spawn_blocking(move || {
-        client.process_text(&prompt_text, &text_clone, &backend, &model)
-    }).await.map_err(|e| format!("Task join error: {}", e))?;
-
-    // Process the result
-    match result {
-        Ok(result) => {
-            println!("✓ Success! Result length: {} chars", result.len());
-            Ok(result)
+        let on_progress = |progress: Progress| {
+            let job_id = job_id_for_chunks.clone();
+            let _ = match progress {
+                Progress::Chunk(text) => app_for_chunks.emit(
+                    "apply-chunk",
+                    ApplyChunk {
+                        job_id,
+                        text: text.to_string(),
+                    },
+                ),
+                Progress::Fallback { from, to, error } => app_for_chunks.emit(
+                    "apply-progress",
+                    ApplyProgress {
+                        job_id,
+                        message: format!("{}: {}. Trying {}…", from, error, to),
+                        restart: true,
+                    },
+                ),
+                Progress::Retry {
+                    backend,
+                    attempt,
+                    max_attempts,
+                    delay,
+                    error,
+                } => app_for_chunks.emit(
+                    "apply-progress",
+                    ApplyProgress {
+                        job_id,
+                        message: format!(
+                            "{}: {}. Retrying ({}/{}) in {:.1}s…",
+                            backend,
+                            error,
+                            attempt,
+                            max_attempts,
+                            delay.as_secs_f64()
+                        ),
+                        restart: true,
+                    },
+                ),
+            };
+        };
+
+        // Each step gets the previous step's output as its text.
+        let total = runs.len();
+        let mut input = text;
+        let mut combined: Option<ApplyResult> = None;
+        for (index, run) in runs.into_iter().enumerate() {
+            if pipeline.is_some() {
+                let _ = app_for_chunks.emit(
+                    "apply-progress",
+                    ApplyProgress {
+                        job_id: job_id_for_chunks.clone(),
+                        message: format!("Step {}/{}: {}…", index + 1, total, run.name),
+                        restart: true,
+                    },
+                );
+            }
+            let request = LLMRequest {
+                user_content: input,
+                ..run.request
+            };
+            let output = client
+                .process_text(&request, &run.backend_id, &config, &job_for_call, &on_progress)
+                .map_err(|error| match &pipeline {
+                    Some(pipeline) => SamwiseError::StepFailed {
+                        pipeline: pipeline.clone(),
+                        step: index + 1,
+                        steps: total,
+                        name: run.name.clone(),
+                        error: Box::new(error),
+                    },
+                    None => error,
+                })?;
+            input = output.text.clone();
+            combined = Some(match combined {
+                Some(earlier) => earlier.followed_by(output),
+                None => output,
+            });
+        }
+        Ok(combined.expect("a prompt has at least one step"))
+    }).await;
+    jobs.finish(&job_id);
+    let result = result.map_err(|e| SamwiseError::failed(&backend_name, e.to_string()))?;
+
+    let _ = app.emit(
+        "apply-done",
+        ApplyDone {
+            job_id: job_id.clone(),
+            success: result.is_ok(),
         },
-        Err(e) => {
-            eprintln!("✗ Error: {}", e);
-            // On error, return helpful message
-            Ok(format!(
-                "[Error: {}]\n\n\
-                Applied: {}\n\n\
-                Original text:\n{}\n\n\
-                Prompt:\n{}\n\n\
-                ℹ️ To fix this:\n\
-                - If using Claude: install and sign in to the Claude CLI (brew install claude)\n\
-                - If using Codex: install and sign in to the Codex CLI (npm install -g @openai/codex)\n\
-                - Pick a backend from the Backend menu",
-                e,
-                prompt.name,
-                text,
-                prompt.system_prompt
-            ))
+    );
+
+    // Nobody is waiting for a cancelled job's output.
+    if job.is_cancelled() {
+        println!("⚠ Job {} cancelled", job_id);
+        return Err(SamwiseError::Cancelled);
+    }
+
+    match &result {
+        Ok(output) => {
+            println!("✓ Success! Result length: {} chars", output.text.len());
+            if let Some(usage) = &output.usage {
+                println!(
+                    "  Tokens: {} in, {} out, cost: {}, {} ms",
+                    usage.input_tokens,
+                    usage.output_tokens,
+                    usage.cost_usd.map_or("n/a".to_string(), |c| format!("${:.4}", c)),
+                    output.duration_ms
+                );
+            }
         }
+        Err(e) => eprintln!("✗ Error: {}", e),
     }
+    result
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -91,6 +380,7 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .plugin(tauri_plugin_clipboard_manager::init())
+        .manage(JobRegistry::default())
         .setup(|app| {
             // Initialize user config directory and copy default prompts if needed
             match Prompt::ensure_user_config() {
@@ -98,12 +388,26 @@ pub fn run() {
                 Err(e) => eprintln!("⚠ Could not ensure user config: {}", e),
             }
 
+            // Read config and prompts once; commands use the copy in state
+            app.manage(AppState::load(app.handle()));
+            let config = app.state::<AppState>().config();
+
+            // Say what is wrong with prompts.yaml up front; the UI shows the
+            // same list through validate_prompts
+            let report = Prompt::check_file(&backend_ids(&config));
+            for issue in &report.issues {
+                eprintln!("⚠ {}: {}", report.path, issue);
+            }
+            if report.has_errors() {
+                eprintln!("✗ {} can't be loaded; using the built-in prompts", report.path);
+            }
+            if let Err(e) = watcher::watch_prompts(app.handle()) {
+                eprintln!("⚠ {} (edit prompts, then use File → Reload Prompts)", e);
+            }
+
             // Create and set up the menu
             menu::create_menu(app)?;
 
-            // Load config to get hotkey preference
-            let config = AppConfig::load(&app.handle());
-
             // Set up global shortcut with configured hotkey
             let app_handle = app.handle().clone();
             match hotkey::setup_global_shortcut(&app_handle, &config.global_hotkey) {
@@ -226,11 +530,21 @@ pub fn run() {
         })
         .invoke_handler(tauri::generate_handler![
             get_prompts,
+            get_prompt_groups,
+            reload_prompts,
+            validate_prompts,
+            get_prompt_variables,
+            create_prompt,
+            update_prompt,
+            delete_prompt,
+            reorder_prompts,
             apply_prompt,
+            jobs::cancel_job,
             config::get_config,
             config::save_config,
-            config::check_claude_cli,
-            config::check_codex_cli,
+            config::list_backends,
+            config::list_models,
+            config::check_backend,
             hotkey::update_global_shortcut
         ])
         .run(tauri::generate_context!())
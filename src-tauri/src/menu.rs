@@ -1,16 +1,52 @@
 // Menu system for Samwise
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
 use tauri::{App, AppHandle, Emitter, Manager, Wry};
 use tauri::menu::{
-    CheckMenuItem, CheckMenuItemBuilder, MenuBuilder, MenuItemBuilder, SubmenuBuilder,
+    CheckMenuItem, CheckMenuItemBuilder, MenuBuilder, MenuItemBuilder, PredefinedMenuItem, Submenu,
+    SubmenuBuilder,
 };
 
 use crate::config::AppConfig;
+use crate::llm_client;
+use crate::prompts::Prompt;
+use crate::state::AppState;
+use crate::watcher;
+
+// Menu ids for backend items are this prefix plus the backend id.
+const BACKEND_ID_PREFIX: &str = "backend_";
+
+// Menu ids for model items are this prefix plus a running number; the
+// backend and model are looked up in `BackendMenu::models`, since both may
+// contain any character.
+const MODEL_ID_PREFIX: &str = "model_";
+
+// Menu ids for prompt items are this prefix plus the prompt id.
+const PROMPT_ID_PREFIX: &str = "prompt_";
 
-// Handles to the backend check items, kept in app state so we can move the
-// checkmark when the backend changes.
-struct BackendMenuItems {
-    claude: CheckMenuItem<Wry>,
-    codex: CheckMenuItem<Wry>,
+// The Prompts submenu, kept in app state so it can be refilled when the
+// prompts change.
+struct PromptsMenu(Submenu<Wry>);
+
+// The Backend submenu and handles to its check items, kept in app state so
+// we can move the checkmarks and refill it when the config changes.
+struct BackendMenu {
+    menu: Submenu<Wry>,
+    // Backend check items, by backend id.
+    backends: Mutex<Vec<(String, CheckMenuItem<Wry>)>>,
+    // Model check items, as (backend id, model, item).
+    models: Mutex<Vec<(String, String, CheckMenuItem<Wry>)>>,
+    // Models found by `list_models`, by backend id. They replace the
+    // backend's quick `models()` list in the menu.
+    discovered: Mutex<HashMap<String, Vec<String>>>,
+}
+
+// Payload of the "model-selected" event.
+#[derive(Clone, serde::Serialize)]
+struct ModelSelected {
+    backend: String,
+    model: String,
 }
 
 pub fn create_menu(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
@@ -19,6 +55,10 @@ pub fn create_menu(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
         .accelerator("CmdOrCtrl+,")
         .build(app)?;
 
+    let reload_prompts_item = MenuItemBuilder::with_id("reload_prompts", "Reload Prompts")
+        .accelerator("CmdOrCtrl+R")
+        .build(app)?;
+
     let exit_item = MenuItemBuilder::with_id("exit", "Exit")
         .accelerator("CmdOrCtrl+Q")
         .build(app)?;
@@ -26,39 +66,35 @@ pub fn create_menu(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
     // Create File submenu
     let file_menu = SubmenuBuilder::new(app, "File")
         .item(&settings_item)
+        .item(&reload_prompts_item)
         .separator()
         .item(&exit_item)
         .build()?;
 
-    // Backend menu: pick which CLI tool runs the text. A checkmark shows which
-    // one is active, so the dropdown makes the current backend obvious.
-    let backend = AppConfig::load(app.handle()).backend;
-
-    let claude_item = CheckMenuItemBuilder::new("Claude CLI")
-        .id("backend_claude")
-        .checked(backend == "claude")
-        .build(app)?;
-
-    let codex_item = CheckMenuItemBuilder::new("Codex")
-        .id("backend_codex")
-        .checked(backend == "codex")
-        .build(app)?;
-
-    let backend_menu = SubmenuBuilder::new(app, "Backend")
-        .item(&claude_item)
-        .item(&codex_item)
-        .build()?;
+    // Backend menu: one check item per registered backend. A checkmark shows
+    // which one is active, so the dropdown makes the current backend obvious.
+    // Below them, a model submenu for each backend that lists its models.
+    let backend_menu = BackendMenu {
+        menu: SubmenuBuilder::new(app, "Backend").build()?,
+        backends: Mutex::default(),
+        models: Mutex::default(),
+        discovered: Mutex::default(),
+    };
+    fill_backend_menu(app.handle(), &backend_menu, &app.state::<AppState>().config())?;
+    let backend_submenu = backend_menu.menu.clone();
+    app.manage(backend_menu);
 
-    // Keep the check items so we can update them later.
-    app.manage(BackendMenuItems {
-        claude: claude_item,
-        codex: codex_item,
-    });
+    // Prompts menu: the visible prompts, grouped as in the window. Picking
+    // one applies it to the input text.
+    let prompts_menu = SubmenuBuilder::new(app, "Prompts").build()?;
+    fill_prompts_menu(app.handle(), &prompts_menu, &app.state::<AppState>().prompts())?;
+    app.manage(PromptsMenu(prompts_menu.clone()));
 
     // Create the main menu
     let menu = MenuBuilder::new(app)
         .item(&file_menu)
-        .item(&backend_menu)
+        .item(&prompts_menu)
+        .item(&backend_submenu)
         .build()?;
 
     // Set the menu for the app
@@ -75,30 +111,193 @@ pub fn handle_menu_event(app: &AppHandle<Wry>, event: tauri::menu::MenuEvent) {
             // Emit event to frontend to open settings
             app.emit("menu-settings", ()).unwrap();
         }
+        "reload_prompts" => {
+            println!("Reload prompts clicked");
+            watcher::reload_prompts(app);
+        }
         "exit" => {
             println!("Exit clicked");
             std::process::exit(0);
         }
 
-        // Backend menu items
-        "backend_claude" => {
-            println!("Selected backend: Claude CLI");
-            set_active_backend(app, "claude");
-            app.emit("backend-selected", "claude").unwrap();
+        // Prompt items: the frontend applies the prompt
+        id if id.starts_with(PROMPT_ID_PREFIX) => {
+            let prompt_id = &id[PROMPT_ID_PREFIX.len()..];
+            println!("Selected prompt: {}", prompt_id);
+            app.emit("prompt-selected", prompt_id).unwrap();
+        }
+
+        // Model items: the frontend saves the choice, as for backends
+        id if id.starts_with(MODEL_ID_PREFIX) => {
+            let Some((backend, model)) = model_item(app, id) else {
+                return;
+            };
+            println!("Selected model: {} for {}", model, backend);
+            set_active_model(app, &backend, &model);
+            app.emit("model-selected", ModelSelected { backend, model }).unwrap();
         }
-        "backend_codex" => {
-            println!("Selected backend: Codex");
-            set_active_backend(app, "codex");
-            app.emit("backend-selected", "codex").unwrap();
+
+        // Backend menu items
+        id => {
+            let config = app.state::<AppState>().config();
+            let Some(backend) = id
+                .strip_prefix(BACKEND_ID_PREFIX)
+                .and_then(|id| llm_client::find_backend(&config, id))
+            else {
+                return;
+            };
+            println!("Selected backend: {}", backend.display_name());
+            set_active_backend(app, backend.id());
+            app.emit("backend-selected", backend.id()).unwrap();
         }
-        _ => {}
     }
 }
 
 // Move the checkmark to the active backend so the dropdown always shows it.
 fn set_active_backend(app: &AppHandle<Wry>, backend: &str) {
-    if let Some(items) = app.try_state::<BackendMenuItems>() {
-        let _ = items.claude.set_checked(backend == "claude");
-        let _ = items.codex.set_checked(backend == "codex");
+    if let Some(menu) = app.try_state::<BackendMenu>() {
+        for (id, item) in menu.backends.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+            let _ = item.set_checked(id == backend);
+        }
     }
 }
+
+// Move the checkmark to the active model within one backend's submenu.
+fn set_active_model(app: &AppHandle<Wry>, backend: &str, model: &str) {
+    if let Some(menu) = app.try_state::<BackendMenu>() {
+        for (item_backend, item_model, item) in menu.models.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+            if item_backend == backend {
+                let _ = item.set_checked(item_model == model);
+            }
+        }
+    }
+}
+
+// The backend and model of the model item with menu id `id`.
+fn model_item(app: &AppHandle<Wry>, id: &str) -> Option<(String, String)> {
+    let menu = app.try_state::<BackendMenu>()?;
+    let models = menu.models.lock().unwrap_or_else(PoisonError::into_inner);
+    models
+        .iter()
+        .find(|(_, _, item)| item.id() == id)
+        .map(|(backend, model, _)| (backend.clone(), model.clone()))
+}
+
+// Rebuild the Backend menu from the config in state, e.g. after it was
+// saved (a new custom backend, a model added to a list, another backend or
+// model picked in Settings).
+pub fn update_backend_menu(app: &AppHandle<Wry>) {
+    if let Some(menu) = app.try_state::<BackendMenu>() {
+        let config = app.state::<AppState>().config();
+        if let Err(e) = fill_backend_menu(app, &menu, &config) {
+            eprintln!("⚠ Could not update the Backend menu: {}", e);
+        }
+    }
+}
+
+// Use the models `list_models` found for `backend` in its model submenu.
+pub fn set_discovered_models(app: &AppHandle<Wry>, backend: &str, models: Vec<String>) {
+    if let Some(menu) = app.try_state::<BackendMenu>() {
+        let mut discovered = menu.discovered.lock().unwrap_or_else(PoisonError::into_inner);
+        if discovered.get(backend) == Some(&models) {
+            return;
+        }
+        discovered.insert(backend.to_string(), models);
+    }
+    update_backend_menu(app);
+}
+
+// Replace the items of the Backend menu: a check item per backend, then a
+// model submenu for each backend with models (found ones, or its quick
+// list).
+fn fill_backend_menu(app: &AppHandle<Wry>, menu: &BackendMenu, config: &AppConfig) -> tauri::Result<()> {
+    for item in menu.menu.items()? {
+        menu.menu.remove(&item)?;
+    }
+    let backends = llm_client::backends(config);
+    let active = config.active_backend();
+
+    let mut backend_items = Vec::new();
+    for b in &backends {
+        let item = CheckMenuItemBuilder::new(b.display_name())
+            .id(format!("{}{}", BACKEND_ID_PREFIX, b.id()))
+            .checked(b.id() == active.id())
+            .build(app)?;
+        menu.menu.append(&item)?;
+        backend_items.push((b.id().to_string(), item));
+    }
+
+    let discovered = menu.discovered.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut model_items = Vec::new();
+    for b in &backends {
+        let models = discovered.get(b.id()).cloned().unwrap_or_else(|| b.models(config));
+        if models.is_empty() {
+            continue;
+        }
+        if model_items.is_empty() {
+            menu.menu.append(&PredefinedMenuItem::separator(app)?)?;
+        }
+        let model = b.model(config);
+        let mut model_menu = SubmenuBuilder::new(app, format!("{} Model", b.display_name()));
+        for name in models {
+            let item = CheckMenuItemBuilder::new(&name)
+                .id(format!("{}{}", MODEL_ID_PREFIX, model_items.len()))
+                .checked(name == model)
+                .build(app)?;
+            model_menu = model_menu.item(&item);
+            model_items.push((b.id().to_string(), name, item));
+        }
+        menu.menu.append(&model_menu.build()?)?;
+    }
+
+    *menu.backends.lock().unwrap_or_else(PoisonError::into_inner) = backend_items;
+    *menu.models.lock().unwrap_or_else(PoisonError::into_inner) = model_items;
+    Ok(())
+}
+
+// Rebuild the Prompts menu after the prompts changed (a reload or an edit).
+pub fn update_prompts_menu(app: &AppHandle<Wry>, prompts: &[Prompt]) {
+    if let Some(menu) = app.try_state::<PromptsMenu>() {
+        if let Err(e) = fill_prompts_menu(app, &menu.0, prompts) {
+            eprintln!("⚠ Could not update the Prompts menu: {}", e);
+        }
+    }
+}
+
+// Replace the items of `menu` with the groups from `Prompt::groups`: pinned
+// and uncategorized prompts as items, each category as a submenu, with
+// separators between the loose items and the submenus.
+fn fill_prompts_menu(app: &AppHandle<Wry>, menu: &Submenu<Wry>, prompts: &[Prompt]) -> tauri::Result<()> {
+    for item in menu.items()? {
+        menu.remove(&item)?;
+    }
+    let mut previous_loose = None;
+    for group in Prompt::groups(prompts) {
+        let loose = group.category.is_none();
+        if previous_loose.is_some_and(|previous| previous || loose) {
+            menu.append(&PredefinedMenuItem::separator(app)?)?;
+        }
+        previous_loose = Some(loose);
+
+        let mut items = Vec::new();
+        for prompt in &group.prompts {
+            let label = format!("{} {}", prompt.icon, prompt.name);
+            items.push(MenuItemBuilder::with_id(format!("{}{}", PROMPT_ID_PREFIX, prompt.id), label).build(app)?);
+        }
+        match group.category {
+            Some(category) => {
+                let mut submenu = SubmenuBuilder::new(app, category);
+                for item in &items {
+                    submenu = submenu.item(item);
+                }
+                menu.append(&submenu.build()?)?;
+            }
+            None => {
+                for item in &items {
+                    menu.append(item)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
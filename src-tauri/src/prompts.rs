@@ -1,29 +1,259 @@
 // Prompt definitions for Samwise
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Prompt {
     pub id: String,
     pub name: String,
     pub description: String,
+    /// Empty for a pipeline, which runs its `steps` instead
+    #[serde(default)]
     pub system_prompt: String,
     pub icon: String,
+    /// Backend id to run this prompt on instead of the one picked in the app
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Model for this prompt; without it the backend's configured model is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Sampling temperature (API backends only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Longest reply, in tokens (API backends only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Seconds before the call is given up on, instead of the backend's timeout
+    #[serde(default, alias = "timeout", skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Ids of prompts to run one after another, each on the previous one's
+    /// output. A prompt with steps is a pipeline and has no system prompt.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<String>,
+    /// Group the prompt is listed under; without one it is listed ungrouped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Position in the list, lowest first; prompts without one come after
+    /// those with one, in file order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<i32>,
+    /// Left out of the prompt list and menu, but still usable as a step
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+    /// Listed first, in a group of its own
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
 }
 
-#[derive(Debug, Deserialize)]
+/// Prompts listed together, for the UI and the Prompts menu
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptGroup {
+    /// `None` for the pinned group and for prompts without a category
+    pub category: Option<String>,
+    pub pinned: bool,
+    pub prompts: Vec<Prompt>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 struct PromptsFile {
     prompts: Vec<Prompt>,
 }
 
+/// Keys a prompt entry may have; anything else is reported as unknown
+const PROMPT_KEYS: &[&str] = &[
+    "id",
+    "name",
+    "description",
+    "system_prompt",
+    "icon",
+    "backend",
+    "model",
+    "temperature",
+    "max_tokens",
+    "timeout_secs",
+    "timeout",
+    "steps",
+    "category",
+    "order",
+    "hidden",
+    "pinned",
+];
+
+/// Keys every prompt entry must have (`system_prompt` only without `steps`)
+const REQUIRED_PROMPT_KEYS: &[&str] = &["id", "name", "description", "system_prompt", "icon"];
+
+/// Put at the top of prompts.yaml when the app saves it
+const SAVED_FILE_NOTE: &str = "# Saved by Samwise. Comments and unknown keys from the previous version
+# were not kept; that version is in prompts.yaml.bak.
+";
+
+/// Placeholders Samwise can fill in by itself
+pub const BUILTIN_VARIABLES: &[&str] = &["date", "clipboard", "user_name"];
+
+/// How bad a problem in prompts.yaml is
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The file can't be loaded
+    Error,
+    /// The file loads, but probably not as intended
+    Warning,
+}
+
+/// One problem found in prompts.yaml
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PromptIssue {
+    pub severity: Severity,
+    pub message: String,
+    /// 1-based position in the file, for YAML and type errors
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The entry the problem is in: its id, or its position ("#3") if it has none
+    pub prompt: Option<String>,
+}
+
+impl PromptIssue {
+    fn error(message: impl Into<String>) -> Self {
+        PromptIssue {
+            severity: Severity::Error,
+            message: message.into(),
+            line: None,
+            column: None,
+            prompt: None,
+        }
+    }
+
+    fn warning(message: impl Into<String>) -> Self {
+        PromptIssue {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    fn in_prompt(self, prompt: &str) -> Self {
+        PromptIssue {
+            prompt: Some(prompt.to_string()),
+            ..self
+        }
+    }
+
+    /// A serde_yaml error, with its position split out of the message
+    fn from_yaml(error: &serde_yaml::Error) -> Self {
+        let message = error.to_string();
+        let message = match error.location() {
+            // The position sits mid-sentence ("... at line 4 column 1, while
+            // parsing ..."); it has fields of its own here.
+            Some(l) => message.replacen(&format!(" at line {} column {}", l.line(), l.column()), "", 1),
+            None => message,
+        };
+        PromptIssue {
+            line: error.location().map(|l| l.line()),
+            column: error.location().map(|l| l.column()),
+            ..Self::error(message)
+        }
+    }
+}
+
+impl std::fmt::Display for PromptIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, "line {}, column {}: ", line, column)?;
+        }
+        if let Some(prompt) = &self.prompt {
+            write!(f, "prompt {}: ", prompt)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Result of checking prompts.yaml, for the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptsReport {
+    pub path: String,
+    pub issues: Vec<PromptIssue>,
+}
+
+impl PromptsReport {
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(|i| i.severity == Severity::Error)
+    }
+}
+
 impl Prompt {
+    /// The prompts to run for this one, in order: itself, or for a pipeline
+    /// its steps, with nested pipelines spelled out. Steps are looked up by
+    /// id in `prompts`. Fails on an unknown id or a pipeline that contains
+    /// itself.
+    pub fn resolve_steps<'a>(&'a self, prompts: &'a [Prompt]) -> Result<Vec<&'a Prompt>, String> {
+        let mut resolved = Vec::new();
+        self.collect_steps(prompts, &mut vec![self.id.as_str()], &mut resolved)?;
+        Ok(resolved)
+    }
+
+    fn collect_steps<'a>(
+        &'a self,
+        prompts: &'a [Prompt],
+        path: &mut Vec<&'a str>,
+        resolved: &mut Vec<&'a Prompt>,
+    ) -> Result<(), String> {
+        if self.steps.is_empty() {
+            resolved.push(self);
+            return Ok(());
+        }
+        for id in &self.steps {
+            if path.contains(&id.as_str()) {
+                return Err(format!("The steps loop back on themselves: {} → {}", path.join(" → "), id));
+            }
+            let step = prompts
+                .iter()
+                .find(|p| &p.id == id)
+                .ok_or_else(|| format!("Step '{}' is not a prompt id", id))?;
+            path.push(id);
+            step.collect_steps(prompts, path, resolved)?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    /// Names of the `{{placeholders}}` in the system prompt, each once
+    pub fn variables(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for (_, name) in placeholders(&self.system_prompt) {
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+        names
+    }
+
+    /// The system prompt with every `{{name}}` replaced by `lookup(name)`.
+    /// Fails with the first name that has no value.
+    pub fn fill_variables(&self, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+        let template = &self.system_prompt;
+        let mut filled = String::with_capacity(template.len());
+        let mut rest = 0;
+        for (range, name) in placeholders(template) {
+            let value = lookup(name).ok_or_else(|| name.to_string())?;
+            filled.push_str(&template[rest..range.start]);
+            filled.push_str(&value);
+            rest = range.end;
+        }
+        filled.push_str(&template[rest..]);
+        Ok(filled)
+    }
+
+    /// Where the user's prompts.yaml goes, whether or not it exists yet
+    pub fn user_prompts_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("samwise").join("prompts.yaml"))
+    }
+
     /// Get the path to the prompts.yaml file
     /// Priority: 1) User config dir, 2) Project root (fallback)
-    fn get_prompts_file_path() -> PathBuf {
+    pub fn get_prompts_file_path() -> PathBuf {
         // Try user config directory first
-        if let Some(config_dir) = dirs::config_dir() {
-            let user_prompts = config_dir.join("samwise").join("prompts.yaml");
+        if let Some(user_prompts) = Self::user_prompts_path() {
             if user_prompts.exists() {
                 return user_prompts;
             }
@@ -41,45 +271,239 @@ impl Prompt {
     pub fn get_all_prompts() -> Vec<Prompt> {
         let prompts_path = Self::get_prompts_file_path();
 
-        let mut prompts = match fs::read_to_string(&prompts_path) {
-            Ok(content) => {
-                match serde_yaml::from_str::<PromptsFile>(&content) {
-                    Ok(prompts_file) => {
-                        println!("✓ Loaded {} prompts from: {:?}", prompts_file.prompts.len(), prompts_path);
-                        prompts_file.prompts
-                    }
-                    Err(e) => {
-                        eprintln!("✗ Failed to parse prompts.yaml: {}", e);
-                        Self::get_default_prompts()
-                    }
-                }
+        match Self::load_from(&prompts_path) {
+            Ok(prompts) => {
+                println!("✓ Loaded {} prompts from: {:?}", prompts.len(), prompts_path);
+                prompts
             }
             Err(e) => {
-                eprintln!("✗ Failed to read prompts.yaml from {:?}: {}", prompts_path, e);
+                eprintln!("✗ {}", e);
                 Self::get_default_prompts()
             }
+        }
+    }
+
+    /// Load prompts from one YAML file, without falling back to the defaults
+    /// (a reload keeps the prompts it has instead). Warnings are logged; the
+    /// first error fails the load.
+    pub fn load_from(path: &Path) -> Result<Vec<Prompt>, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let issues = Self::validate(&content);
+        for issue in issues.iter().filter(|i| i.severity == Severity::Warning) {
+            eprintln!("⚠ {}: {}", path.display(), issue);
+        }
+        if let Some(error) = issues.iter().find(|i| i.severity == Severity::Error) {
+            return Err(format!("Failed to parse {}: {}", path.display(), error));
+        }
+        let mut prompts = serde_yaml::from_str::<PromptsFile>(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+            .prompts;
+        Self::sort(&mut prompts);
+        Ok(prompts)
+    }
+
+    /// Put prompts in display order: pinned ones first, then "raw", then by
+    /// `order`. Ties keep their file order.
+    pub fn sort(prompts: &mut [Prompt]) {
+        prompts.sort_by_key(|p| (!p.pinned, p.id != "raw", p.order.is_none(), p.order));
+    }
+
+    /// The visible prompts in groups, for a sorted list: the pinned ones,
+    /// then one group per category (prompts without one form a group too),
+    /// in the order their first prompt comes in.
+    pub fn groups(prompts: &[Prompt]) -> Vec<PromptGroup> {
+        let mut groups: Vec<PromptGroup> = Vec::new();
+        for prompt in prompts.iter().filter(|p| !p.hidden) {
+            let category = if prompt.pinned {
+                None
+            } else {
+                prompt.category.clone().filter(|c| !c.trim().is_empty())
+            };
+            match groups.iter_mut().find(|g| g.pinned == prompt.pinned && g.category == category) {
+                Some(group) => group.prompts.push(prompt.clone()),
+                None => groups.push(PromptGroup {
+                    category,
+                    pinned: prompt.pinned,
+                    prompts: vec![prompt.clone()],
+                }),
+            }
+        }
+        groups
+    }
+
+    /// Write `prompts` to `path`, replacing the file. The new text is checked
+    /// first and nothing is written if `validate` finds an error; warnings
+    /// are only logged, as on load. The old file is kept next to it as
+    /// `prompts.yaml.bak`, and the new one is moved into place whole, so a
+    /// crash mid-write leaves the old file as it was. The file is rewritten
+    /// from `prompts`, so comments and unknown keys in the old one are lost;
+    /// a note at the top of the new file says so.
+    pub fn save_to(path: &Path, prompts: &[Prompt]) -> Result<(), String> {
+        let file = PromptsFile {
+            prompts: prompts.to_vec(),
         };
+        let yaml = serde_yaml::to_string(&file).map_err(|e| format!("Failed to write the prompts: {}", e))?;
+        let content = format!("{}{}", SAVED_FILE_NOTE, yaml);
+        let issues = Self::validate(&content);
+        if let Some(error) = issues.iter().find(|i| i.severity == Severity::Error) {
+            return Err(format!("Not saved: {}", error));
+        }
+        for warning in &issues {
+            eprintln!("⚠ {}: {}", path.display(), warning);
+        }
 
-        // Ensure "raw" prompt is always first
-        prompts.sort_by(|a, b| {
-            match (a.id.as_str(), b.id.as_str()) {
-                ("raw", _) => std::cmp::Ordering::Less,
-                (_, "raw") => std::cmp::Ordering::Greater,
-                _ => std::cmp::Ordering::Equal,
+        let write_failed = |e: std::io::Error| format!("Failed to write {}: {}", path.display(), e);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(write_failed)?;
+        }
+        let temp = path.with_extension("yaml.tmp");
+        let mut out = fs::File::create(&temp).map_err(write_failed)?;
+        out.write_all(content.as_bytes())
+            .and_then(|_| out.sync_all())
+            .map_err(write_failed)?;
+        if path.exists() {
+            fs::copy(path, path.with_extension("yaml.bak")).map_err(write_failed)?;
+        }
+        fs::rename(&temp, path).map_err(write_failed)
+    }
+
+    /// Check the prompts.yaml in use and list every problem found, including
+    /// prompts that name a backend not among `backends` (the ids in use)
+    pub fn check_file(backends: &[String]) -> PromptsReport {
+        let path = Self::get_prompts_file_path();
+        let issues = match fs::read_to_string(&path) {
+            Ok(content) => {
+                let mut issues = Self::validate(&content);
+                if !issues.iter().any(|i| i.severity == Severity::Error) {
+                    issues.extend(Self::unknown_backends(&content, backends));
+                }
+                issues
             }
-        });
+            Err(e) => vec![PromptIssue::error(format!("Failed to read the file: {}", e))],
+        };
+        PromptsReport {
+            path: path.display().to_string(),
+            issues,
+        }
+    }
 
-        prompts
+    /// Prompts whose `backend` isn't one of `backends`. The backends depend on
+    /// the config (custom ones), so `validate` can't tell on its own.
+    fn unknown_backends(content: &str, backends: &[String]) -> Vec<PromptIssue> {
+        let Ok(file) = serde_yaml::from_str::<PromptsFile>(content) else {
+            return Vec::new();
+        };
+        file.prompts
+            .iter()
+            .filter_map(|prompt| {
+                let backend = prompt.backend.as_ref().filter(|b| !backends.contains(b))?;
+                Some(
+                    PromptIssue::warning(format!("Unknown backend `{}`; known: {}", backend, backends.join(", ")))
+                        .in_prompt(&format!("'{}'", prompt.id)),
+                )
+            })
+            .collect()
+    }
+
+    /// Find problems in the text of a prompts file: YAML errors (with line
+    /// and column), missing fields, unknown keys, empty names, duplicate ids,
+    /// a `model` without a `backend` and pipelines with unknown or looping
+    /// steps
+    pub fn validate(content: &str) -> Vec<PromptIssue> {
+        let document: serde_yaml::Value = match serde_yaml::from_str(content) {
+            Ok(document) => document,
+            Err(e) => return vec![PromptIssue::from_yaml(&e)],
+        };
+        let Some(file) = document.as_mapping() else {
+            return vec![PromptIssue::error("The file should be a mapping with a `prompts:` list")];
+        };
+
+        let mut issues = Vec::new();
+        for key in file.keys() {
+            if key.as_str() != Some("prompts") {
+                issues.push(PromptIssue::warning(format!("Unknown top-level key `{}`", yaml_key(key))));
+            }
+        }
+        let Some(entries) = file.get("prompts").and_then(|p| p.as_sequence()) else {
+            issues.push(PromptIssue::error("Missing the `prompts:` list"));
+            return issues;
+        };
+
+        let mut seen_ids: Vec<&str> = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            let id = entry.get("id").and_then(|id| id.as_str()).filter(|id| !id.is_empty());
+            let label = id.map_or_else(|| format!("#{}", index + 1), |id| format!("'{}'", id));
+            let Some(fields) = entry.as_mapping() else {
+                issues.push(PromptIssue::error("Each prompt should be a mapping of fields").in_prompt(&label));
+                continue;
+            };
+
+            // A pipeline has steps instead of a system prompt
+            let is_pipeline = fields.contains_key("steps");
+            let required = REQUIRED_PROMPT_KEYS.iter().filter(|key| !is_pipeline || **key != "system_prompt");
+            for key in required {
+                if !fields.contains_key(*key) {
+                    issues.push(PromptIssue::error(format!("Missing field `{}`", key)).in_prompt(&label));
+                }
+            }
+            for key in fields.keys() {
+                if !key.as_str().is_some_and(|key| PROMPT_KEYS.contains(&key)) {
+                    issues.push(PromptIssue::warning(format!("Unknown key `{}`", yaml_key(key))).in_prompt(&label));
+                }
+            }
+            if fields.contains_key("model") && !fields.contains_key("backend") {
+                issues.push(
+                    PromptIssue::warning("`model` needs a `backend`; it can't be applied without one").in_prompt(&label),
+                );
+            }
+            if fields.get("id").and_then(|id| id.as_str()) == Some("") {
+                issues.push(PromptIssue::error("The id is empty").in_prompt(&label));
+            }
+            if fields.get("name").and_then(|name| name.as_str()).is_some_and(|name| name.trim().is_empty()) {
+                issues.push(PromptIssue::warning("The name is empty, so its button has no label").in_prompt(&label));
+            }
+            if let Some(id) = id {
+                if seen_ids.contains(&id) {
+                    issues.push(
+                        PromptIssue::warning("Duplicate id; only the first prompt with this id can be applied")
+                            .in_prompt(&label),
+                    );
+                }
+                seen_ids.push(id);
+            }
+        }
+
+        // Wrong types (a list where text belongs, ...) only show up when
+        // reading the file for real; serde_yaml knows where they are.
+        if issues.iter().any(|i| i.severity == Severity::Error) {
+            return issues;
+        }
+        let prompts = match serde_yaml::from_str::<PromptsFile>(content) {
+            Ok(file) => file.prompts,
+            Err(e) => {
+                issues.push(PromptIssue::from_yaml(&e));
+                return issues;
+            }
+        };
+
+        // A broken pipeline fails only when it is applied; the other prompts
+        // still work.
+        for prompt in prompts.iter().filter(|p| !p.steps.is_empty()) {
+            if let Err(e) = prompt.resolve_steps(&prompts) {
+                issues.push(PromptIssue::warning(e).in_prompt(&format!("'{}'", prompt.id)));
+            }
+        }
+        issues
     }
 
     /// Copy default prompts.yaml to user config directory
     pub fn ensure_user_config() -> Result<PathBuf, std::io::Error> {
-        if let Some(config_dir) = dirs::config_dir() {
-            let samwise_config = config_dir.join("samwise");
-            let user_prompts = samwise_config.join("prompts.yaml");
-
+        if let Some(user_prompts) = Self::user_prompts_path() {
             // Create directory if it doesn't exist
-            fs::create_dir_all(&samwise_config)?;
+            if let Some(samwise_config) = user_prompts.parent() {
+                fs::create_dir_all(samwise_config)?;
+            }
 
             // Copy default prompts if user version doesn't exist
             if !user_prompts.exists() {
@@ -114,6 +538,7 @@ impl Prompt {
                 description: "Talk directly to the LLM - your text goes straight to the model".to_string(),
                 system_prompt: "".to_string(),
                 icon: "💬".to_string(),
+                ..Default::default()
             },
             Prompt {
                 id: "fix_grammar".to_string(),
@@ -121,6 +546,7 @@ impl Prompt {
                 description: "Fix only grammar, spelling, and punctuation".to_string(),
                 system_prompt: "Fix only the grammar, spelling, and punctuation of the text. Do not reword, rephrase, restructure, or change the style, tone, or vocabulary. Change a word only when it is grammatically wrong. If the text is already correct, return it exactly as it is.".to_string(),
                 icon: "✓".to_string(),
+                ..Default::default()
             },
             Prompt {
                 id: "improve_text".to_string(),
@@ -128,6 +554,7 @@ impl Prompt {
                 description: "Make it clearer and easier to read".to_string(),
                 system_prompt: "Rewrite the text so it reads more clearly and naturally. Fix awkward phrasing, tighten wordy parts, and improve the flow. Keep the original meaning, tone, and language. Do not add new ideas or change the facts.".to_string(),
                 icon: "✨".to_string(),
+                ..Default::default()
             },
             Prompt {
                 id: "summarize".to_string(),
@@ -135,6 +562,7 @@ impl Prompt {
                 description: "Shorten to the key points".to_string(),
                 system_prompt: "Summarize the text in about one third of its length. Keep the main ideas and the important details. Do not add opinions or new information.".to_string(),
                 icon: "📝".to_string(),
+                ..Default::default()
             },
             Prompt {
                 id: "expand".to_string(),
@@ -142,6 +570,7 @@ impl Prompt {
                 description: "Add helpful detail and context".to_string(),
                 system_prompt: "Expand the text with helpful detail, context, and examples. Keep the same tone and meaning. Do not change the original facts or add unrelated ideas.".to_string(),
                 icon: "📖".to_string(),
+                ..Default::default()
             },
             Prompt {
                 id: "simplify".to_string(),
@@ -149,6 +578,7 @@ impl Prompt {
                 description: "Make it easy for anyone to read".to_string(),
                 system_prompt: "Rewrite the text so it is easy to read at about an 8th-grade level. Use short sentences and simple, everyday words. Keep all the meaning. Do not leave anything important out.".to_string(),
                 icon: "💡".to_string(),
+                ..Default::default()
             },
             Prompt {
                 id: "professional".to_string(),
@@ -156,6 +586,7 @@ impl Prompt {
                 description: "Polished, business-ready tone".to_string(),
                 system_prompt: "Rewrite the text in a polished, professional tone for business use. Keep it clear and direct. Remove slang, casual phrases, and emojis. Keep the original meaning.".to_string(),
                 icon: "💼".to_string(),
+                ..Default::default()
             },
             Prompt {
                 id: "fact_check".to_string(),
@@ -163,6 +594,7 @@ impl Prompt {
                 description: "Check claims and flag problems".to_string(),
                 system_prompt: "Check the text for factual and logical problems. List each claim, say whether it looks correct, questionable, or wrong, and note anything missing or unsupported. Be brief and clear.".to_string(),
                 icon: "🔍".to_string(),
+                ..Default::default()
             },
             Prompt {
                 id: "make_concise".to_string(),
@@ -170,8 +602,477 @@ impl Prompt {
                 description: "Cut the fluff, keep the meaning".to_string(),
                 system_prompt: "Make the text shorter while keeping every important point. Cut filler, repetition, and weak words. Keep the meaning and tone. Aim for about half the length.".to_string(),
                 icon: "⚡".to_string(),
+                ..Default::default()
             },
         ]
     }
 }
 
+/// Add `prompt` at the end. Its id must not be taken.
+pub fn create_prompt(prompts: &mut Vec<Prompt>, prompt: Prompt) -> Result<(), String> {
+    if prompts.iter().any(|p| p.id == prompt.id) {
+        return Err(format!("A prompt with id '{}' already exists", prompt.id));
+    }
+    prompts.push(prompt);
+    Ok(())
+}
+
+/// Replace the prompt `id` with `prompt`, in the same place. If the id
+/// changes, pipelines that use it as a step follow along.
+pub fn update_prompt(prompts: &mut [Prompt], id: &str, prompt: Prompt) -> Result<(), String> {
+    let index = position(prompts, id)?;
+    if prompt.id != id {
+        if prompts.iter().any(|p| p.id == prompt.id) {
+            return Err(format!("A prompt with id '{}' already exists", prompt.id));
+        }
+        for step in prompts.iter_mut().flat_map(|p| p.steps.iter_mut()) {
+            if step == id {
+                step.clone_from(&prompt.id);
+            }
+        }
+    }
+    prompts[index] = prompt;
+    Ok(())
+}
+
+/// Remove the prompt `id`. Refused while a pipeline uses it as a step.
+pub fn delete_prompt(prompts: &mut Vec<Prompt>, id: &str) -> Result<(), String> {
+    let index = position(prompts, id)?;
+    let users: Vec<&str> = prompts
+        .iter()
+        .filter(|p| p.steps.iter().any(|step| step == id))
+        .map(|p| p.id.as_str())
+        .collect();
+    if !users.is_empty() {
+        return Err(format!(
+            "'{}' is a step of {}; take it out of those pipelines first",
+            id,
+            users.iter().map(|u| format!("'{}'", u)).collect::<Vec<_>>().join(", ")
+        ));
+    }
+    prompts.remove(index);
+    Ok(())
+}
+
+/// Put the prompts in the order of `ids`, which must name each prompt once.
+/// If any prompt has an `order`, all of them are renumbered to match.
+/// Pinned prompts and "raw" still come first once sorted (see `Prompt::sort`).
+pub fn reorder_prompts(prompts: &mut [Prompt], ids: &[String]) -> Result<(), String> {
+    let mut given: Vec<&str> = ids.iter().map(String::as_str).collect();
+    let mut current: Vec<&str> = prompts.iter().map(|p| p.id.as_str()).collect();
+    given.sort_unstable();
+    current.sort_unstable();
+    if given != current {
+        return Err("The new order must list every prompt id exactly once".to_string());
+    }
+    prompts.sort_by_key(|p| ids.iter().position(|id| *id == p.id));
+    if prompts.iter().any(|p| p.order.is_some()) {
+        for (order, prompt) in (1..).zip(prompts.iter_mut()) {
+            prompt.order = Some(order);
+        }
+    }
+    Ok(())
+}
+
+fn position(prompts: &[Prompt], id: &str) -> Result<usize, String> {
+    prompts
+        .iter()
+        .position(|p| p.id == id)
+        .ok_or_else(|| format!("No prompt with id '{}'", id))
+}
+
+/// Each `{{name}}` in `text` (spaces inside the braces allowed): where it is
+/// and the name. Braces around anything but a plain name are left alone.
+fn placeholders(text: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(open) = text[from..].find("{{").map(|i| from + i) {
+        let Some(close) = text[open + 2..].find("}}").map(|i| open + 2 + i) else {
+            break;
+        };
+        let name = text[open + 2..close].trim();
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            found.push((open..close + 2, name));
+            from = close + 2;
+        } else {
+            from = open + 2;
+        }
+    }
+    found
+}
+
+/// A mapping key as written in the file, for messages
+fn yaml_key(key: &serde_yaml::Value) -> String {
+    match key.as_str() {
+        Some(key) => key.to_string(),
+        None => serde_yaml::to_string(key).unwrap_or_default().trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_prompts(yaml: &str) -> PathBuf {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "samwise-prompts-{}-{}.yaml",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::write(&path, yaml).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_from_puts_raw_first() {
+        let path = write_prompts(
+            "prompts:
+  - { id: fix, name: Fix, description: d, system_prompt: s, icon: x }
+  - { id: raw, name: Raw, description: d, system_prompt: '', icon: y }
+",
+        );
+        let ids: Vec<String> = Prompt::load_from(&path).unwrap().into_iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec!["raw", "fix"]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn load_from_sorts_by_pinned_then_order() {
+        let path = write_prompts(
+            "prompts:
+  - { id: a, name: A, description: d, system_prompt: s, icon: x }
+  - { id: b, name: B, description: d, system_prompt: s, icon: x, order: 2 }
+  - { id: raw, name: Raw, description: d, system_prompt: '', icon: y }
+  - { id: c, name: C, description: d, system_prompt: s, icon: x, order: 1 }
+  - { id: d, name: D, description: d, system_prompt: s, icon: x, pinned: true }
+  - { id: e, name: E, description: d, system_prompt: s, icon: x }
+",
+        );
+        let ids: Vec<String> = Prompt::load_from(&path).unwrap().into_iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec!["d", "raw", "c", "b", "a", "e"]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn groups_pinned_then_categories_and_skips_hidden() {
+        let prompt = |id: &str, category: Option<&str>| Prompt {
+            id: id.to_string(),
+            category: category.map(str::to_string),
+            ..Prompt::default()
+        };
+        let prompts = vec![
+            Prompt {
+                pinned: true,
+                ..prompt("fav", Some("Style"))
+            },
+            prompt("raw", None),
+            prompt("fix", Some("Style")),
+            prompt("translate", Some("Language")),
+            Prompt {
+                hidden: true,
+                ..prompt("step", Some("Language"))
+            },
+            prompt("concise", Some("Style")),
+            prompt("summarize", None),
+        ];
+        let groups: Vec<(Option<String>, bool, Vec<String>)> = Prompt::groups(&prompts)
+            .into_iter()
+            .map(|g| (g.category, g.pinned, g.prompts.into_iter().map(|p| p.id).collect()))
+            .collect();
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            groups,
+            vec![
+                (None, true, ids(&["fav"])),
+                (None, false, ids(&["raw", "summarize"])),
+                (Some("Style".to_string()), false, ids(&["fix", "concise"])),
+                (Some("Language".to_string()), false, ids(&["translate"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_from_reports_parse_errors_instead_of_defaults() {
+        let path = write_prompts("prompts:\n  - id: [unclosed\n");
+        let err = Prompt::load_from(&path).unwrap_err();
+        assert!(err.starts_with("Failed to parse"), "{}", err);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn validate_reports_yaml_errors_with_line_and_column() {
+        let issues = Prompt::validate("prompts:\n  - id: fix\n    name: [unclosed\n");
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!((issues[0].line, issues[0].column), (Some(4), Some(1)), "{:?}", issues[0]);
+        assert!(issues[0].message.starts_with("did not find expected ',' or ']', while parsing"), "{}", issues[0].message);
+    }
+
+    #[test]
+    fn validate_reports_every_problem_in_the_entries() {
+        let issues = Prompt::validate(
+            "prompts:
+  - { id: fix, name: Fix, description: d, system_prompt: s, icon: x }
+  - { id: fix, name: '  ', description: d, system_promt: s, icon: x }
+  - { name: No id, description: d, system_prompt: s, icon: x }
+",
+        );
+        let found: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            found,
+            vec![
+                "prompt 'fix': Missing field `system_prompt`",
+                "prompt 'fix': Unknown key `system_promt`",
+                "prompt 'fix': The name is empty, so its button has no label",
+                "prompt 'fix': Duplicate id; only the first prompt with this id can be applied",
+                "prompt #3: Missing field `id`",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_wants_a_backend_with_a_model() {
+        let issues = Prompt::validate(
+            "prompts:
+  - { id: fix, name: Fix, description: d, system_prompt: s, icon: x, model: opus }
+  - { id: check, name: Check, description: d, system_prompt: s, icon: x, backend: claude, model: opus }
+",
+        );
+        let found: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(found, vec!["prompt 'fix': `model` needs a `backend`; it can't be applied without one"]);
+    }
+
+    #[test]
+    fn unknown_backends_are_reported() {
+        let backends = vec!["claude".to_string(), "ollama".to_string()];
+        let issues = Prompt::unknown_backends(
+            "prompts:
+  - { id: fix, name: Fix, description: d, system_prompt: s, icon: x, backend: claude }
+  - { id: check, name: Check, description: d, system_prompt: s, icon: x, backend: olama }
+",
+            &backends,
+        );
+        let found: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(found, vec!["prompt 'check': Unknown backend `olama`; known: claude, ollama"]);
+    }
+
+    #[test]
+    fn validate_finds_wrong_types_with_their_location() {
+        let issues = Prompt::validate(
+            "prompts:
+  - id: fix
+    name: [not, text]
+    description: d
+    system_prompt: s
+    icon: x
+",
+        );
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].line, Some(3));
+    }
+
+    fn prompt(system_prompt: &str) -> Prompt {
+        Prompt {
+            id: "translate".to_string(),
+            name: "Translate".to_string(),
+            description: String::new(),
+            system_prompt: system_prompt.to_string(),
+            icon: String::new(),
+            ..Prompt::default()
+        }
+    }
+
+    #[test]
+    fn fills_variables_and_leaves_other_braces_alone() {
+        let translate = prompt("Translate into {{language}} ({{ language }}), {{target_length}} words. Keep {{ x-y }} and {{}}.");
+        assert_eq!(translate.variables(), vec!["language", "target_length"]);
+
+        let filled = translate.fill_variables(|name| match name {
+            "language" => Some("French".to_string()),
+            "target_length" => Some("50".to_string()),
+            _ => None,
+        });
+        assert_eq!(filled.unwrap(), "Translate into French (French), 50 words. Keep {{ x-y }} and {{}}.");
+    }
+
+    #[test]
+    fn fill_variables_names_the_missing_value() {
+        let translate = prompt("Translate into {{language}}.");
+        assert_eq!(translate.fill_variables(|_| None), Err("language".to_string()));
+    }
+
+    fn pipeline(id: &str, steps: &[&str]) -> Prompt {
+        Prompt {
+            id: id.to_string(),
+            steps: steps.iter().map(|s| s.to_string()).collect(),
+            ..Prompt::default()
+        }
+    }
+
+    fn step_ids(prompt: &Prompt, prompts: &[Prompt]) -> Result<Vec<String>, String> {
+        prompt
+            .resolve_steps(prompts)
+            .map(|steps| steps.iter().map(|p| p.id.clone()).collect())
+    }
+
+    #[test]
+    fn resolves_nested_pipeline_steps_in_order() {
+        let prompts = vec![
+            pipeline("fix", &[]),
+            pipeline("concise", &[]),
+            pipeline("polish", &["fix", "concise"]),
+            pipeline("pro", &["polish", "fix"]),
+        ];
+        assert_eq!(step_ids(&prompts[0], &prompts).unwrap(), vec!["fix"]);
+        assert_eq!(step_ids(&prompts[3], &prompts).unwrap(), vec!["fix", "concise", "fix"]);
+    }
+
+    #[test]
+    fn resolve_steps_reports_loops_and_unknown_ids() {
+        let prompts = vec![
+            pipeline("a", &["b"]),
+            pipeline("b", &["fix", "a"]),
+            pipeline("fix", &[]),
+            pipeline("typo", &["fix", "fixx"]),
+        ];
+        assert_eq!(
+            step_ids(&prompts[0], &prompts),
+            Err("The steps loop back on themselves: a → b → a".to_string())
+        );
+        assert_eq!(step_ids(&prompts[3], &prompts), Err("Step 'fixx' is not a prompt id".to_string()));
+    }
+
+    #[test]
+    fn validate_accepts_pipelines_and_warns_about_broken_ones() {
+        let issues = Prompt::validate(
+            "prompts:
+  - { id: fix, name: Fix, description: d, system_prompt: s, icon: x }
+  - { id: polish, name: Polish, description: d, icon: x, steps: [fix] }
+  - { id: loop, name: Loop, description: d, icon: x, steps: [fix, loop] }
+",
+        );
+        let found: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(found, vec!["prompt 'loop': The steps loop back on themselves: loop → loop"]);
+        assert_eq!(issues[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn save_to_round_trips_and_keeps_a_backup() {
+        let path = write_prompts(
+            "prompts:
+  - { id: fix, name: Fix, description: d, system_prompt: s, icon: x }
+",
+        );
+        let mut prompts = Prompt::load_from(&path).unwrap();
+        prompts.push(Prompt {
+            id: "polish".to_string(),
+            name: "Polish".to_string(),
+            steps: vec!["fix".to_string()],
+            temperature: Some(0.2),
+            ..Prompt::default()
+        });
+        Prompt::save_to(&path, &prompts).unwrap();
+
+        let saved = Prompt::load_from(&path).unwrap();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[1].steps, vec!["fix"]);
+        assert_eq!(saved[1].temperature, Some(0.2));
+        let backup = path.with_extension("yaml.bak");
+        assert_eq!(Prompt::load_from(&backup).unwrap().len(), 1);
+        assert!(!path.with_extension("yaml.tmp").exists());
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(backup);
+    }
+
+    #[test]
+    fn save_to_refuses_errors_but_not_warnings() {
+        let path = write_prompts("prompts: []\n");
+        let prompts = vec![pipeline("", &[])];
+        let err = Prompt::save_to(&path, &prompts).unwrap_err();
+        assert_eq!(err, "Not saved: prompt #1: The id is empty");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "prompts: []\n");
+
+        // An empty name is only a warning, so it doesn't stop the save.
+        Prompt::save_to(&path, &[pipeline("fix", &[])]).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with(SAVED_FILE_NOTE), "{}", saved);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("yaml.bak"));
+    }
+
+    fn ids(prompts: &[Prompt]) -> Vec<&str> {
+        prompts.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    #[test]
+    fn create_prompt_rejects_a_taken_id() {
+        let mut prompts = vec![pipeline("fix", &[])];
+        create_prompt(&mut prompts, pipeline("concise", &[])).unwrap();
+        assert_eq!(ids(&prompts), vec!["fix", "concise"]);
+        assert_eq!(
+            create_prompt(&mut prompts, pipeline("fix", &[])),
+            Err("A prompt with id 'fix' already exists".to_string())
+        );
+        assert_eq!(prompts.len(), 2);
+    }
+
+    #[test]
+    fn update_prompt_renames_steps_and_rejects_a_taken_id() {
+        let mut prompts = vec![pipeline("fix", &[]), pipeline("concise", &[]), pipeline("polish", &["fix", "concise"])];
+        update_prompt(&mut prompts, "fix", pipeline("grammar", &[])).unwrap();
+        assert_eq!(ids(&prompts), vec!["grammar", "concise", "polish"]);
+        assert_eq!(prompts[2].steps, vec!["grammar", "concise"]);
+
+        assert_eq!(
+            update_prompt(&mut prompts, "grammar", pipeline("concise", &[])),
+            Err("A prompt with id 'concise' already exists".to_string())
+        );
+        assert_eq!(
+            update_prompt(&mut prompts, "fix", pipeline("fix", &[])),
+            Err("No prompt with id 'fix'".to_string())
+        );
+    }
+
+    #[test]
+    fn delete_prompt_refuses_a_step_in_use() {
+        let mut prompts = vec![pipeline("fix", &[]), pipeline("concise", &[]), pipeline("polish", &["fix"])];
+        assert_eq!(
+            delete_prompt(&mut prompts, "fix"),
+            Err("'fix' is a step of 'polish'; take it out of those pipelines first".to_string())
+        );
+        delete_prompt(&mut prompts, "concise").unwrap();
+        assert_eq!(ids(&prompts), vec!["fix", "polish"]);
+    }
+
+    #[test]
+    fn reorder_prompts_checks_the_ids_and_renumbers_order() {
+        let order = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let mut prompts = vec![pipeline("a", &[]), pipeline("b", &[]), pipeline("c", &[])];
+        assert!(reorder_prompts(&mut prompts, &order(&["c", "a"])).is_err());
+        assert!(reorder_prompts(&mut prompts, &order(&["c", "a", "a"])).is_err());
+
+        reorder_prompts(&mut prompts, &order(&["c", "a", "b"])).unwrap();
+        assert_eq!(ids(&prompts), vec!["c", "a", "b"]);
+        assert!(prompts.iter().all(|p| p.order.is_none()));
+
+        prompts[0].order = Some(5);
+        reorder_prompts(&mut prompts, &order(&["b", "c", "a"])).unwrap();
+        assert_eq!(ids(&prompts), vec!["b", "c", "a"]);
+        assert_eq!(prompts.iter().map(|p| p.order).collect::<Vec<_>>(), vec![Some(1), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn warnings_do_not_stop_a_load() {
+        let path = write_prompts(
+            "prompts:
+  - { id: fix, name: Fix, description: d, system_prompt: s, icon: x, colour: red }
+",
+        );
+        assert_eq!(Prompt::load_from(&path).unwrap().len(), 1);
+        let _ = fs::remove_file(path);
+    }
+}
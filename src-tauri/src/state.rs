@@ -0,0 +1,88 @@
+// Config and prompts, kept in Tauri state.
+//
+// Both are read from disk once at startup. Commands read the copy in memory,
+// so applying a prompt costs no file reads (and no log lines). Whatever
+// changes them on disk (`save_config`, the hotkey update, a prompts reload,
+// the prompt editing commands) puts the new value here too.
+
+use std::sync::{PoisonError, RwLock};
+
+use tauri::AppHandle;
+
+use crate::config::AppConfig;
+use crate::prompts::{self, Prompt};
+
+pub struct AppState {
+    config: RwLock<AppConfig>,
+    prompts: RwLock<Vec<Prompt>>,
+}
+
+impl AppState {
+    pub fn load(app: &AppHandle) -> Self {
+        AppState {
+            config: RwLock::new(AppConfig::load(app)),
+            prompts: RwLock::new(Prompt::get_all_prompts()),
+        }
+    }
+
+    // A copy, so no lock is held while a backend runs.
+    pub fn config(&self) -> AppConfig {
+        self.config.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    // Write the config to disk, then use it. Nothing changes if the write
+    // fails.
+    pub fn save_config(&self, app: &AppHandle, config: AppConfig) -> Result<(), String> {
+        config.save(app)?;
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = config;
+        Ok(())
+    }
+
+    pub fn prompts(&self) -> Vec<Prompt> {
+        self.prompts.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    // Read prompts.yaml again, e.g. after it was edited by hand. If it can't
+    // be read or parsed, the prompts in use stay as they are.
+    pub fn reload_prompts(&self) -> Result<Vec<Prompt>, String> {
+        let prompts = Prompt::load_from(&Prompt::get_prompts_file_path())?;
+        *self.prompts.write().unwrap_or_else(PoisonError::into_inner) = prompts.clone();
+        Ok(prompts)
+    }
+
+    // The editing commands; see the functions of the same name in
+    // prompts.rs for the rules.
+    pub fn create_prompt(&self, prompt: Prompt) -> Result<Vec<Prompt>, String> {
+        self.edit_prompts(|prompts| prompts::create_prompt(prompts, prompt))
+    }
+
+    pub fn update_prompt(&self, id: &str, prompt: Prompt) -> Result<Vec<Prompt>, String> {
+        self.edit_prompts(|prompts| prompts::update_prompt(prompts, id, prompt))
+    }
+
+    pub fn delete_prompt(&self, id: &str) -> Result<Vec<Prompt>, String> {
+        self.edit_prompts(|prompts| prompts::delete_prompt(prompts, id))
+    }
+
+    pub fn reorder_prompts(&self, ids: &[String]) -> Result<Vec<Prompt>, String> {
+        self.edit_prompts(|prompts| prompts::reorder_prompts(prompts, ids))
+    }
+
+    // Read prompts.yaml, change it with `edit` and save it to the user's
+    // config dir (see `Prompt::save_to`), then use the result. The file is
+    // read fresh so hand edits aren't lost; one that doesn't load can't be
+    // edited this way. The lock is held throughout so edits don't interleave.
+    fn edit_prompts(
+        &self,
+        edit: impl FnOnce(&mut Vec<Prompt>) -> Result<(), String>,
+    ) -> Result<Vec<Prompt>, String> {
+        let mut current = self.prompts.write().unwrap_or_else(PoisonError::into_inner);
+        let mut prompts = Prompt::load_from(&Prompt::get_prompts_file_path())?;
+        edit(&mut prompts)?;
+        Prompt::sort(&mut prompts);
+        let path = Prompt::user_prompts_path().ok_or("Could not find the config directory")?;
+        Prompt::save_to(&path, &prompts)?;
+        *current = prompts.clone();
+        Ok(prompts)
+    }
+}
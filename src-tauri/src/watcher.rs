@@ -0,0 +1,75 @@
+// Reloads prompts.yaml when it changes on disk, so edits show up without a
+// restart.
+//
+// The folder is watched rather than the file: many editors save by writing a
+// new file and renaming it over the old one, which a watch on the file itself
+// would miss.
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::menu;
+use crate::prompts::Prompt;
+use crate::state::AppState;
+
+// Kept in Tauri state; dropping it would stop the watch.
+struct PromptsWatcher {
+    _debouncer: Mutex<Debouncer<RecommendedWatcher>>,
+}
+
+// Start watching the prompts file that `Prompt::get_prompts_file_path`
+// resolves to.
+pub fn watch_prompts(app: &AppHandle) -> Result<(), String> {
+    let path = Prompt::get_prompts_file_path();
+    let dir = path
+        .parent()
+        .ok_or_else(|| format!("{} has no parent folder", path.display()))?
+        .to_path_buf();
+    let file_name = path.file_name().map(|name| name.to_os_string());
+
+    let app_handle = app.clone();
+    // Saving often touches the file several times in a row; wait for quiet.
+    let mut debouncer = new_debouncer(Duration::from_millis(300), move |result: DebounceEventResult| {
+        match result {
+            Ok(events) => {
+                if events.iter().any(|e| e.path.file_name().map(|n| n.to_os_string()) == file_name) {
+                    reload_prompts(&app_handle);
+                }
+            }
+            Err(e) => eprintln!("⚠ Watching prompts failed: {}", e),
+        }
+    })
+    .map_err(|e| format!("Failed to start the prompts watcher: {}", e))?;
+
+    debouncer
+        .watcher()
+        .watch(Path::new(&dir), RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Failed to watch {}: {}", dir.display(), e))?;
+
+    app.manage(PromptsWatcher {
+        _debouncer: Mutex::new(debouncer),
+    });
+    println!("✓ Watching for prompt changes: {:?}", path);
+    Ok(())
+}
+
+// Read prompts.yaml into state and tell the frontend: `prompts-updated` with
+// the new list, or `prompts-error` with the reason the old list was kept.
+pub fn reload_prompts(app: &AppHandle) {
+    match app.state::<AppState>().reload_prompts() {
+        Ok(prompts) => {
+            println!("✓ Reloaded {} prompts", prompts.len());
+            menu::update_prompts_menu(app, &prompts);
+            let _ = app.emit("prompts-updated", prompts);
+        }
+        Err(e) => {
+            eprintln!("✗ {}; keeping the prompts already loaded", e);
+            let _ = app.emit("prompts-error", e);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-import { useState, useEffect } from "react";
+import { useState, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import "./App.css";
@@ -9,17 +9,201 @@ interface Prompt {
   description: string;
   system_prompt: string;
   icon: string;
+  // Per-prompt overrides, see Prompt in prompts.rs; unset = global settings
+  backend?: string;
+  model?: string;
+  temperature?: number;
+  max_tokens?: number;
+  timeout_secs?: number;
+  steps?: string[]; // a pipeline: prompt ids run in turn, system_prompt empty
+  category?: string;
+  order?: number; // lowest first; the backend sorts
+  hidden?: boolean; // left out of the grid and menu, still usable as a step
+  pinned?: boolean;
 }
 
+// Visible prompts as listed in the window, see PromptGroup in prompts.rs.
+interface PromptGroup {
+  category: string | null; // null for the pinned group and uncategorized prompts
+  pinned: boolean;
+  prompts: Prompt[];
+}
+
+// Problems in prompts.yaml, see PromptsReport in prompts.rs.
+interface PromptIssue {
+  severity: "error" | "warning";
+  message: string;
+  line: number | null; // 1-based, for YAML and type errors
+  column: number | null;
+  prompt: string | null; // "'id'" or "#3"
+}
+
+interface PromptsReport {
+  path: string;
+  issues: PromptIssue[];
+}
+
+// Where an issue is, then what it is, for the prompts banner.
+function describeIssue(issue: PromptIssue): string {
+  const where: string[] = [];
+  if (issue.line != null) where.push(`line ${issue.line}, column ${issue.column}`);
+  if (issue.prompt) where.push(`prompt ${issue.prompt}`);
+  return where.length ? `${where.join(", ")}: ${issue.message}` : issue.message;
+}
+
+// Payload of the "apply-chunk" event.
+interface ApplyChunk {
+  job_id: string;
+  text: string;
+}
+
+// Payload of the "apply-progress" event: a status line. With restart set,
+// the output streamed so far is void (a fallback backend starts over).
+interface ApplyProgress {
+  job_id: string;
+  message: string;
+  restart: boolean;
+}
+
+// Result of apply_prompt, see ApplyResult in llm_client.rs.
+interface ApplyResult {
+  text: string;
+  usage: {
+    input_tokens: number;
+    output_tokens: number;
+    cost_usd: number | null; // only the Claude CLI reports a cost
+  } | null;
+  duration_ms: number;
+  model: string; // empty = backend default
+  backend: string; // id of the backend that answered, may be a fallback
+}
+
+// One line under the result: model, tokens, cost and time.
+function describeUsage(result: ApplyResult): string {
+  const parts: string[] = [];
+  if (result.model) parts.push(result.model);
+  if (result.usage) {
+    parts.push(`${result.usage.input_tokens.toLocaleString()} in / ${result.usage.output_tokens.toLocaleString()} out tokens`);
+    if (result.usage.cost_usd != null) parts.push(`$${result.usage.cost_usd.toFixed(4)}`);
+  }
+  parts.push(`${(result.duration_ms / 1000).toFixed(1)}s`);
+  return parts.join(" · ");
+}
+
+// Error returned by apply_prompt, see SamwiseError in error.rs.
+type SamwiseError =
+  | { kind: "NotInstalled"; backend: string; hint: string }
+  | { kind: "NotAuthenticated"; backend: string; message: string }
+  | { kind: "RateLimited"; backend: string; message: string }
+  | { kind: "Overloaded"; backend: string; message: string }
+  | { kind: "Timeout"; backend: string; secs: number }
+  | { kind: "PromptNotFound"; id: string }
+  | { kind: "MissingVariable"; prompt: string; name: string }
+  | { kind: "InvalidConfig"; message: string }
+  | { kind: "StepFailed"; pipeline: string; step: number; steps: number; name: string; error: SamwiseError }
+  | { kind: "BackendFailed"; backend: string; stderr: string }
+  | { kind: "Cancelled" };
+
+// What went wrong and how to fix it, for the output box.
+function describeError(error: unknown): string {
+  const err = error as SamwiseError;
+  switch (err?.kind) {
+    case "NotInstalled":
+      return `${err.backend} isn't available.\n\n${err.hint}\nOr pick another backend from the Backend menu.`;
+    case "NotAuthenticated":
+      return `${err.backend} isn't signed in, or its API key was rejected.\n\n${err.message}\n\nSign in to the CLI, or check the API key in Settings.`;
+    case "RateLimited":
+      return `${err.backend} is rate limited.\n\n${err.message}\n\nWait a moment and try again, or switch backends.`;
+    case "Overloaded":
+      return `${err.backend} is overloaded and couldn't take the request, even after retrying.\n\n${err.message}\n\nTry again in a moment, or switch backends.`;
+    case "Timeout":
+      return `${err.backend} didn't answer within ${err.secs}s and was stopped.\n\nTry again, or raise the timeout in Settings.`;
+    case "PromptNotFound":
+      return `Prompt "${err.id}" no longer exists. Reload the prompts (File → Reload Prompts) and try again.`;
+    case "MissingVariable":
+      return `The "${err.prompt}" prompt needs a value for {{${err.name}}}.\n\nFill it in under Variables and try again.`;
+    case "InvalidConfig":
+      return `Configuration problem: ${err.message}`;
+    case "StepFailed":
+      return `Step ${err.step} of ${err.steps} (${err.name}) failed:\n\n${describeError(err.error)}`;
+    case "BackendFailed":
+      return `${err.backend} failed:\n\n${err.stderr}`;
+    case "Cancelled":
+      return "Operation cancelled by user.";
+    default:
+      return `Error: ${error}`;
+  }
+}
+
+interface BackendInfo {
+  id: string;
+  name: string;
+  available: boolean;
+  models: string[]; // empty = free text
+}
+
+// Per-backend settings, see BackendOptions in config.rs.
+interface BackendOptions {
+  timeout_secs: number; // 0 = no limit
+  extra_args?: string[]; // CLI backends only; edit in config.json
+  env?: Record<string, string>; // CLI backends only; edit in config.json
+  models?: string[]; // CLI backends only; replaces the built-in model list
+}
+
+// A backend defined in config.json as a command template, see CustomBackend
+// in config.rs.
+interface CustomBackend {
+  id: string;
+  name: string;
+  command: string[];
+  version_command: string[];
+  model: string;
+  models: string[];
+}
+
+// Matches default_timeout_secs in config.rs.
+const DEFAULT_TIMEOUT_SECS = 180;
+
 interface AppConfig {
-  backend: string; // "claude" or "codex"
+  backend: string; // backend id, see list_backends
   claude_model: string; // "haiku" | "sonnet" | "opus" | full id
   codex_model: string; // empty = Codex default
+  anthropic_api_key: string; // empty = ANTHROPIC_API_KEY env var
+  anthropic_model: string; // full model id
+  anthropic_base_url: string;
+  openai_base_url: string; // includes /v1
+  openai_model: string; // empty = server default
+  openai_api_key: string; // empty = OPENAI_API_KEY env var, or none
+  ollama_base_url: string;
+  ollama_model: string; // empty = first installed model
+  custom_backends: CustomBackend[]; // edit in config.json
+  prompt_variables: Record<string, string>; // values for {{placeholders}}
+  fallback_backends: string[]; // tried in order when the active backend fails
+  backend_options: Record<string, BackendOptions>; // by backend id
   global_hotkey: string;
 }
 
+// Config field holding each backend's model.
+const MODEL_FIELDS: Record<string, keyof AppConfig> = {
+  claude: "claude_model",
+  codex: "codex_model",
+  anthropic: "anthropic_model",
+  openai: "openai_model",
+  ollama: "ollama_model",
+};
+
 function App() {
   const [prompts, setPrompts] = useState<Prompt[]>([]);
+  // The visible prompts, grouped for the grid (get_prompt_groups).
+  const [promptGroups, setPromptGroups] = useState<PromptGroup[]>([]);
+  // What's wrong with prompts.yaml, if anything (validate_prompts).
+  const [promptsReport, setPromptsReport] = useState<PromptsReport | null>(null);
+  // {{placeholders}} the prompts use that need a value from the user, and
+  // the values typed in (saved to prompt_variables on blur).
+  const [variableNames, setVariableNames] = useState<string[]>([]);
+  const [variableValues, setVariableValues] = useState<Record<string, string>>({});
+  // The prompt open in the Settings editor: originalId is null for a new one.
+  const [editingPrompt, setEditingPrompt] = useState<{ originalId: string | null; prompt: Prompt } | null>(null);
   const [inputText, setInputText] = useState("");
   const [outputText, setOutputText] = useState("");
   const [isLoading, setIsLoading] = useState(false);
@@ -27,19 +211,31 @@ function App() {
   const [selectedBackend, setSelectedBackend] = useState<string>("claude");
   const [showSettings, setShowSettings] = useState(false);
   const [config, setConfig] = useState<AppConfig | null>(null);
-  const [claudeCliAvailable, setClaudeCliAvailable] = useState(false);
-  const [codexCliAvailable, setCodexCliAvailable] = useState(false);
-  const [isCancelled, setIsCancelled] = useState(false);
+  const [backends, setBackends] = useState<BackendInfo[]>([]);
+  // Models the selected backend offers, from list_models; shown in Settings.
+  const [modelOptions, setModelOptions] = useState<string[]>([]);
+  // Usage line for the last finished call; empty while running or after errors.
+  const [usageText, setUsageText] = useState("");
+  // Job id of the apply_prompt call in flight; null when none (or cancelled).
+  const currentJobId = useRef<string | null>(null);
+  // applyPrompt as of the latest render, for the "prompt-selected" listener
+  // (set up once, so it would otherwise see the first render's input text).
+  const applyPromptRef = useRef<(promptId: string) => void>(() => {});
   const [showInputStroke, setShowInputStroke] = useState(false);
 
   useEffect(() => {
     loadPrompts();
+    checkPrompts();
+    loadVariableNames();
     loadConfig();
-    checkClaudeCli();
-    checkCodexCli();
+    loadBackends();
     setupMenuListeners();
   }, []);
 
+  useEffect(() => {
+    if (showSettings) loadModelOptions(selectedBackend);
+  }, [showSettings, selectedBackend]);
+
   async function setupMenuListeners() {
     // Listen for menu events from Rust
     await listen("menu-settings", () => {
@@ -68,6 +264,60 @@ function App() {
       }
     });
 
+    // prompts.yaml changed on disk (or File → Reload Prompts)
+    await listen<Prompt[]>("prompts-updated", (event) => {
+      setPrompts(event.payload);
+      loadPromptGroups();
+      checkPrompts();
+      loadVariableNames();
+    });
+
+    // prompts.yaml changed but couldn't be loaded; the old prompts stay
+    await listen<string>("prompts-error", () => {
+      checkPrompts();
+    });
+
+    // A prompt picked from the Prompts menu
+    await listen<string>("prompt-selected", (event) => {
+      applyPromptRef.current(event.payload);
+    });
+
+    // A model picked from a backend's model submenu
+    await listen<{ backend: string; model: string }>("model-selected", async (event) => {
+      const { backend, model } = event.payload;
+      console.log("Model selected:", backend, model);
+      try {
+        const currentConfig = await invoke<AppConfig>("get_config");
+        const field = MODEL_FIELDS[backend];
+        const newConfig = field
+          ? { ...currentConfig, [field]: model }
+          : {
+              ...currentConfig,
+              custom_backends: currentConfig.custom_backends.map((custom) =>
+                custom.id === backend ? { ...custom, model } : custom
+              ),
+            };
+        await saveConfig(newConfig);
+      } catch (error) {
+        console.error("Failed to save the selected model:", error);
+      }
+    });
+
+    // Streamed output for the running job
+    await listen<ApplyChunk>("apply-chunk", (event) => {
+      if (event.payload.job_id === currentJobId.current) {
+        setOutputText((prev) => prev + event.payload.text);
+      }
+    });
+
+    // Status lines for the running job, e.g. a fallback backend taking over
+    await listen<ApplyProgress>("apply-progress", (event) => {
+      if (event.payload.job_id === currentJobId.current) {
+        if (event.payload.restart) setOutputText("");
+        setUsageText(event.payload.message);
+      }
+    });
+
     // Listen for global hotkey trigger
     await listen<string>("hotkey-triggered", (event) => {
       console.log("Hotkey triggered! Clipboard text:", event.payload);
@@ -81,6 +331,22 @@ function App() {
     });
   }
 
+  async function checkPrompts() {
+    try {
+      setPromptsReport(await invoke<PromptsReport>("validate_prompts"));
+    } catch (error) {
+      console.error("Failed to check prompts:", error);
+    }
+  }
+
+  async function loadVariableNames() {
+    try {
+      setVariableNames(await invoke<string[]>("get_prompt_variables"));
+    } catch (error) {
+      console.error("Failed to load prompt variables:", error);
+    }
+  }
+
   async function loadPrompts() {
     try {
       const loadedPrompts = await invoke<Prompt[]>("get_prompts");
@@ -88,6 +354,15 @@ function App() {
     } catch (error) {
       console.error("Failed to load prompts:", error);
     }
+    loadPromptGroups();
+  }
+
+  async function loadPromptGroups() {
+    try {
+      setPromptGroups(await invoke<PromptGroup[]>("get_prompt_groups"));
+    } catch (error) {
+      console.error("Failed to load prompt groups:", error);
+    }
   }
 
   async function loadConfig() {
@@ -95,39 +370,87 @@ function App() {
       const loadedConfig = await invoke<AppConfig>("get_config");
       setConfig(loadedConfig);
       setSelectedBackend(loadedConfig.backend);
+      setVariableValues(loadedConfig.prompt_variables || {});
     } catch (error) {
       console.error("Failed to load config:", error);
     }
   }
 
+  // Run one of the prompt editing commands (create_prompt, update_prompt,
+  // delete_prompt, reorder_prompts); each returns the saved list.
+  async function editPrompts(command: string, args: Record<string, unknown>): Promise<boolean> {
+    try {
+      setPrompts(await invoke<Prompt[]>(command, args));
+      loadPromptGroups();
+      checkPrompts();
+      loadVariableNames();
+      return true;
+    } catch (error) {
+      console.error(`Failed to ${command}:`, error);
+      alert(`Failed to save prompts: ${error}`);
+      return false;
+    }
+  }
+
+  async function saveEditedPrompt() {
+    if (!editingPrompt) return;
+    const { originalId, prompt } = editingPrompt;
+    const saved = originalId === null
+      ? await editPrompts("create_prompt", { prompt })
+      : await editPrompts("update_prompt", { id: originalId, prompt });
+    if (saved) setEditingPrompt(null);
+  }
+
+  async function deletePrompt(prompt: Prompt) {
+    if (!confirm(`Delete the "${prompt.name}" prompt?`)) return;
+    await editPrompts("delete_prompt", { id: prompt.id });
+  }
+
+  // Move the prompt at index one place up (-1) or down (1).
+  async function movePrompt(index: number, by: number) {
+    const ids = prompts.map((p) => p.id);
+    const target = index + by;
+    if (target < 0 || target >= ids.length) return;
+    [ids[index], ids[target]] = [ids[target], ids[index]];
+    await editPrompts("reorder_prompts", { ids });
+  }
+
   async function saveConfig(newConfig: AppConfig) {
     try {
       await invoke("save_config", { config: newConfig });
       setConfig(newConfig);
     } catch (error) {
       console.error("Failed to save config:", error);
-      alert("Failed to save configuration");
+      alert(`Failed to save configuration: ${error}`);
     }
   }
 
-  async function checkClaudeCli() {
+  async function loadBackends() {
     try {
-      const available = await invoke<boolean>("check_claude_cli");
-      setClaudeCliAvailable(available);
+      const loadedBackends = await invoke<BackendInfo[]>("list_backends");
+      setBackends(loadedBackends);
     } catch (error) {
-      console.error("Failed to check Claude CLI:", error);
+      console.error("Failed to load backends:", error);
     }
   }
 
-  async function checkCodexCli() {
+  // Ask the backend for its models; falls back to the list_backends list
+  // when the service can't be reached.
+  async function loadModelOptions(backend: string) {
     try {
-      const available = await invoke<boolean>("check_codex_cli");
-      setCodexCliAvailable(available);
+      setModelOptions(await invoke<string[]>("list_models", { backend }));
     } catch (error) {
-      console.error("Failed to check Codex CLI:", error);
+      console.error("Failed to list models:", error);
+      setModelOptions(backends.find((b) => b.id === backend)?.models || []);
     }
   }
 
+  // Options for a model picker, keeping the current value even if the
+  // backend no longer lists it.
+  function modelChoices(current: string): string[] {
+    return current && !modelOptions.includes(current) ? [current, ...modelOptions] : modelOptions;
+  }
+
   async function updateHotkey(newHotkey: string) {
     if (!config) return;
 
@@ -166,39 +489,59 @@ function App() {
     }
 
     // Set loading state first
+    const jobId = crypto.randomUUID();
+    currentJobId.current = jobId;
     setIsLoading(true);
     setSelectedPrompt(promptId);
     setOutputText("");
-    setIsCancelled(false);
+    setUsageText("");
 
     // Use queueMicrotask to ensure React renders the loading UI before invoking backend
     queueMicrotask(() => {
-      invoke<string>("apply_prompt", {
+      invoke<ApplyResult>("apply_prompt", {
         promptId,
         text: inputText,
+        jobId,
+        variables: variableValues,
       })
         .then((result) => {
-          // Only update output if not cancelled
-          if (!isCancelled) {
-            setOutputText(result);
+          // The final, cleaned text replaces the streamed chunks.
+          // Ignore it if the job was cancelled or replaced.
+          if (currentJobId.current === jobId) {
+            setOutputText(result.text);
+            const usage = describeUsage(result);
+            setUsageText(
+              result.backend === selectedBackend
+                ? usage
+                : `via ${getBackendDisplayName(result.backend)} · ${usage}`
+            );
           }
         })
         .catch((error) => {
           console.error("Error applying prompt:", error);
-          // Only show error if not cancelled
-          if (!isCancelled) {
-            setOutputText(`Error: ${error}`);
+          if (currentJobId.current === jobId) {
+            setOutputText(describeError(error));
+            setUsageText("");
           }
         })
         .finally(() => {
-          setIsLoading(false);
-          setIsCancelled(false);
+          if (currentJobId.current === jobId) {
+            currentJobId.current = null;
+            setIsLoading(false);
+          }
         });
     });
   }
 
   function cancelOperation() {
-    setIsCancelled(true);
+    // Kill the running backend call, not just hide its output.
+    const jobId = currentJobId.current;
+    if (jobId) {
+      invoke("cancel_job", { jobId }).catch((error) =>
+        console.error("Error cancelling job:", error)
+      );
+    }
+    currentJobId.current = null;
     setIsLoading(false);
     setOutputText("Operation cancelled by user.");
   }
@@ -206,6 +549,7 @@ function App() {
   function clearAll() {
     setInputText("");
     setOutputText("");
+    setUsageText("");
     setSelectedPrompt(null);
   }
 
@@ -229,25 +573,40 @@ function App() {
   }
 
   function getBackendDisplayName(backend: string): string {
-    const names: Record<string, string> = {
-      claude: "Claude CLI",
-      codex: "Codex",
-    };
-    return names[backend] || backend;
+    return backends.find((b) => b.id === backend)?.name || backend;
+  }
+
+  // Tooltip for a prompt card: its description, plus where it runs when the
+  // prompt picks its own backend or model.
+  function describePrompt(prompt: Prompt): string {
+    const runsOn = [
+      prompt.backend && getBackendDisplayName(prompt.backend),
+      prompt.model,
+    ].filter(Boolean);
+    const lines = [prompt.description];
+    if (prompt.steps?.length) {
+      const names = prompt.steps.map((id) => prompts.find((p) => p.id === id)?.name ?? id);
+      lines.push(`Steps: ${names.join(" → ")}`);
+    }
+    if (runsOn.length) lines.push(`Runs on ${runsOn.join(" · ")}`);
+    return lines.join("\n");
   }
 
   // The model in use for the active backend, capitalized for display.
   function getActiveModelLabel(): string {
     if (!config) return "";
-    const model = selectedBackend === "codex" ? config.codex_model : config.claude_model;
+    const field = MODEL_FIELDS[selectedBackend];
+    const model = field ? config[field] : "";
     if (!model) return "default";
     return model.charAt(0).toUpperCase() + model.slice(1);
   }
 
+  applyPromptRef.current = applyPrompt;
+
   return (
     <>
-      {/* Loading overlay at top level to ensure it's always visible */}
-      {isLoading && (
+      {/* Loading overlay until the first streamed output arrives */}
+      {isLoading && !outputText && (
         <div className="loading-overlay">
           <div className="loading-content">
             <div className="spinner"></div>
@@ -277,22 +636,44 @@ function App() {
           </div>
 
           <h2>Choose an Action</h2>
-          <div className="prompts-grid">
-            {prompts.map((prompt) => (
-              <button
-                key={prompt.id}
-                className={`prompt-card ${
-                  selectedPrompt === prompt.id ? "selected" : ""
-                }`}
-                onClick={() => applyPrompt(prompt.id)}
-                disabled={isLoading}
-                title={prompt.description}
-              >
-                <span className="prompt-icon">{prompt.icon}</span>
-                <span className="prompt-name">{prompt.name}</span>
-              </button>
-            ))}
-          </div>
+          {promptsReport && promptsReport.issues.length > 0 && (
+            <div className="prompts-error" title={promptsReport.path}>
+              {promptsReport.issues.some((issue) => issue.severity === "error")
+                ? "⚠ prompts.yaml can't be loaded, so the prompts below are the last ones that loaded (or the built-in ones):"
+                : "⚠ prompts.yaml loaded with warnings:"}
+              <ul>
+                {promptsReport.issues.map((issue, index) => (
+                  <li key={index}>{describeIssue(issue)}</li>
+                ))}
+              </ul>
+            </div>
+          )}
+          {promptGroups.map((group) => (
+            <div key={group.pinned ? "pinned" : group.category ?? ""} className="prompt-group">
+              {/* Headings only once there is more than one group */}
+              {promptGroups.length > 1 && (
+                <h3 className="prompt-group-title">
+                  {group.pinned ? "📌 Pinned" : group.category ?? "Other"}
+                </h3>
+              )}
+              <div className="prompts-grid">
+                {group.prompts.map((prompt) => (
+                  <button
+                    key={prompt.id}
+                    className={`prompt-card ${
+                      selectedPrompt === prompt.id ? "selected" : ""
+                    }`}
+                    onClick={() => applyPrompt(prompt.id)}
+                    disabled={isLoading}
+                    title={describePrompt(prompt)}
+                  >
+                    <span className="prompt-icon">{prompt.icon}</span>
+                    <span className="prompt-name">{prompt.name}</span>
+                  </button>
+                ))}
+              </div>
+            </div>
+          ))}
         </div>
 
         <div className="text-section">
@@ -308,6 +689,23 @@ function App() {
               disabled={isLoading}
               className={showInputStroke ? "input-stroke-animation" : ""}
             />
+            {variableNames.length > 0 && (
+              <div className="prompt-variables">
+                <strong>Variables</strong>
+                {variableNames.map((name) => (
+                  <label key={name}>
+                    {name}
+                    <input
+                      type="text"
+                      value={variableValues[name] || ""}
+                      disabled={isLoading}
+                      onChange={(e) => setVariableValues({ ...variableValues, [name]: e.target.value })}
+                      onBlur={() => config && saveConfig({ ...config, prompt_variables: variableValues })}
+                    />
+                  </label>
+                ))}
+              </div>
+            )}
           </div>
 
           {outputText && (
@@ -317,6 +715,11 @@ function App() {
                   <strong>Result</strong>
                 </label>
                 <div className="output-actions">
+                  {isLoading && (
+                    <button className="clear-btn" onClick={cancelOperation}>
+                      ⏹ Stop
+                    </button>
+                  )}
                   <button
                     className="copy-btn"
                     onClick={copyToClipboard}
@@ -339,6 +742,7 @@ function App() {
                 readOnly
                 placeholder="Transformed text will appear here..."
               />
+              {usageText && <p className="usage-info">{usageText}</p>}
             </div>
           )}
         </div>
@@ -395,38 +799,58 @@ function App() {
                   <strong>Backend</strong>
                 </label>
                 <div className="backend-choice">
-                  <label>
-                    <input
-                      type="radio"
-                      name="backend"
-                      value="claude"
-                      checked={selectedBackend === "claude"}
-                      onChange={() => {
-                        setSelectedBackend("claude");
-                        saveConfig({ ...config, backend: "claude" });
-                      }}
-                    />
-                    {" "}Claude CLI
-                  </label>
-                  <label>
-                    <input
-                      type="radio"
-                      name="backend"
-                      value="codex"
-                      checked={selectedBackend === "codex"}
-                      onChange={() => {
-                        setSelectedBackend("codex");
-                        saveConfig({ ...config, backend: "codex" });
-                      }}
-                    />
-                    {" "}Codex
-                  </label>
+                  {backends.map((backend) => (
+                    <label key={backend.id}>
+                      <input
+                        type="radio"
+                        name="backend"
+                        value={backend.id}
+                        checked={selectedBackend === backend.id}
+                        onChange={() => {
+                          setSelectedBackend(backend.id);
+                          saveConfig({ ...config, backend: backend.id });
+                        }}
+                      />
+                      {" "}{backend.name}
+                    </label>
+                  ))}
                 </div>
                 <p className="setting-hint">
                   You can also switch from the "Backend" menu.
                 </p>
               </div>
 
+              <div className="setting-group">
+                <label>
+                  <strong>Fallback backends</strong>
+                </label>
+                <div className="backend-choice">
+                  {backends
+                    .filter((backend) => backend.id !== selectedBackend)
+                    .map((backend) => (
+                      <label key={backend.id}>
+                        <input
+                          type="checkbox"
+                          checked={(config.fallback_backends || []).includes(backend.id)}
+                          onChange={(e) => {
+                            const current = config.fallback_backends || [];
+                            saveConfig({
+                              ...config,
+                              fallback_backends: e.target.checked
+                                ? [...current, backend.id]
+                                : current.filter((id) => id !== backend.id),
+                            });
+                          }}
+                        />
+                        {" "}{backend.name}
+                      </label>
+                    ))}
+                </div>
+                <p className="setting-hint">
+                  Tried in the order you tick them when the active backend isn't installed or signed in, is rate limited or overloaded, or times out.
+                </p>
+              </div>
+
               {selectedBackend === "claude" && (
                 <div className="setting-group">
                   <label>
@@ -439,9 +863,11 @@ function App() {
                       saveConfig({ ...config, claude_model: e.target.value });
                     }}
                   >
-                    <option value="haiku">Haiku (fastest)</option>
-                    <option value="sonnet">Sonnet (balanced)</option>
-                    <option value="opus">Opus (best)</option>
+                    {modelChoices(config.claude_model).map((model) => (
+                      <option key={model} value={model}>
+                        {model.charAt(0).toUpperCase() + model.slice(1)}
+                      </option>
+                    ))}
                   </select>
                   <p className="setting-hint">
                     Haiku is fast and works well for these short edits. Pick Sonnet or Opus for harder text.
@@ -454,39 +880,283 @@ function App() {
                   <label>
                     <strong>Codex Model</strong>
                   </label>
+                  <select
+                    className="model-select"
+                    value={config.codex_model}
+                    onChange={(e) => {
+                      saveConfig({ ...config, codex_model: e.target.value });
+                    }}
+                  >
+                    <option value="">Codex default</option>
+                    {modelChoices(config.codex_model).map((model) => (
+                      <option key={model} value={model}>{model}</option>
+                    ))}
+                  </select>
+                  <p className="setting-hint">
+                    "Codex default" uses whatever model Codex is set up with. Newer models can be added under <code>backend_options.codex.models</code> in config.json.
+                  </p>
+                </div>
+              )}
+
+              {selectedBackend === "anthropic" && (
+                <div className="setting-group">
+                  <label>
+                    <strong>Anthropic API Key</strong>
+                  </label>
+                  <input
+                    type="password"
+                    className="api-key-input"
+                    placeholder="Leave empty to use ANTHROPIC_API_KEY"
+                    value={config.anthropic_api_key}
+                    onChange={(e) => {
+                      setConfig({ ...config, anthropic_api_key: e.target.value });
+                    }}
+                    onBlur={() => {
+                      saveConfig(config).then(() => loadModelOptions(selectedBackend));
+                      loadBackends();
+                    }}
+                  />
+                  <label>
+                    <strong>Anthropic Model</strong>
+                  </label>
+                  <select
+                    className="model-select"
+                    value={config.anthropic_model}
+                    onChange={(e) => {
+                      saveConfig({ ...config, anthropic_model: e.target.value });
+                    }}
+                  >
+                    {modelChoices(config.anthropic_model).map((model) => (
+                      <option key={model} value={model}>{model}</option>
+                    ))}
+                  </select>
+                  <p className="setting-hint">
+                    Calls the Anthropic API directly, no CLI needed. Get a key at console.anthropic.com.
+                  </p>
+                </div>
+              )}
+
+              {selectedBackend === "openai" && (
+                <div className="setting-group">
+                  <label>
+                    <strong>Base URL</strong>
+                  </label>
                   <input
                     type="text"
                     className="api-key-input"
-                    placeholder="Leave empty for Codex default"
-                    value={config.codex_model}
+                    placeholder="https://api.openai.com/v1"
+                    value={config.openai_base_url}
+                    onChange={(e) => {
+                      setConfig({ ...config, openai_base_url: e.target.value });
+                    }}
+                    onBlur={() => {
+                      saveConfig(config).then(() => loadModelOptions(selectedBackend));
+                      loadBackends();
+                    }}
+                  />
+                  <label>
+                    <strong>Model</strong>
+                  </label>
+                  <input
+                    type="text"
+                    className="api-key-input"
+                    placeholder="Leave empty for the server default"
+                    list="openai-models"
+                    value={config.openai_model}
                     onChange={(e) => {
-                      setConfig({ ...config, codex_model: e.target.value });
+                      setConfig({ ...config, openai_model: e.target.value });
                     }}
                     onBlur={() => saveConfig(config)}
                   />
+                  <datalist id="openai-models">
+                    {modelOptions.map((model) => (
+                      <option key={model} value={model} />
+                    ))}
+                  </datalist>
+                  <label>
+                    <strong>API Key</strong>
+                  </label>
+                  <input
+                    type="password"
+                    className="api-key-input"
+                    placeholder="Leave empty to use OPENAI_API_KEY (or none for local servers)"
+                    value={config.openai_api_key}
+                    onChange={(e) => {
+                      setConfig({ ...config, openai_api_key: e.target.value });
+                    }}
+                    onBlur={() => {
+                      saveConfig(config).then(() => loadModelOptions(selectedBackend));
+                      loadBackends();
+                    }}
+                  />
                   <p className="setting-hint">
-                    Optional. Leave empty to use whatever model Codex is set up with.
+                    Works with OpenAI, Azure-style proxies, LM Studio (http://localhost:1234/v1), vLLM and other compatible servers.
                   </p>
                 </div>
               )}
 
+              {selectedBackend === "ollama" && (
+                <div className="setting-group">
+                  <label>
+                    <strong>Ollama URL</strong>
+                  </label>
+                  <input
+                    type="text"
+                    className="api-key-input"
+                    placeholder="http://localhost:11434"
+                    value={config.ollama_base_url}
+                    onChange={(e) => {
+                      setConfig({ ...config, ollama_base_url: e.target.value });
+                    }}
+                    onBlur={() => {
+                      saveConfig(config).then(() => loadModelOptions(selectedBackend));
+                      loadBackends();
+                    }}
+                  />
+                  <label>
+                    <strong>Ollama Model</strong>
+                  </label>
+                  <select
+                    className="model-select"
+                    value={config.ollama_model}
+                    onChange={(e) => {
+                      saveConfig({ ...config, ollama_model: e.target.value });
+                    }}
+                  >
+                    <option value="">First installed model</option>
+                    {modelChoices(config.ollama_model).map((model) => (
+                      <option key={model} value={model}>{model}</option>
+                    ))}
+                  </select>
+                  <p className="setting-hint">
+                    Runs fully offline. Start the daemon with <code>ollama serve</code> and pull a model with <code>ollama pull llama3.2</code>.
+                  </p>
+                </div>
+              )}
+
+              <div className="setting-group">
+                <label>
+                  <strong>Timeout (seconds)</strong>
+                </label>
+                <input
+                  type="number"
+                  min={0}
+                  className="api-key-input"
+                  value={config.backend_options?.[selectedBackend]?.timeout_secs ?? DEFAULT_TIMEOUT_SECS}
+                  onChange={(e) => {
+                    const timeout_secs = Math.max(0, parseInt(e.target.value, 10) || 0);
+                    saveConfig({
+                      ...config,
+                      backend_options: {
+                        ...config.backend_options,
+                        [selectedBackend]: { ...config.backend_options?.[selectedBackend], timeout_secs },
+                      },
+                    });
+                  }}
+                />
+                <p className="setting-hint">
+                  A call to this backend is stopped after this long. 0 means no limit.
+                </p>
+              </div>
+
+              <div className="setting-group">
+                <label>
+                  <strong>Prompts</strong>
+                </label>
+                <ul className="prompt-editor-list">
+                  {prompts.map((prompt, index) => (
+                    <li key={prompt.id}>
+                      <span>{prompt.icon} {prompt.name}{prompt.hidden ? " (hidden)" : ""}</span>
+                      <button onClick={() => movePrompt(index, -1)} disabled={index === 0} title="Move up">↑</button>
+                      <button onClick={() => movePrompt(index, 1)} disabled={index === prompts.length - 1} title="Move down">↓</button>
+                      <button onClick={() => setEditingPrompt({ originalId: prompt.id, prompt: { ...prompt } })}>Edit</button>
+                      <button onClick={() => deletePrompt(prompt)}>Delete</button>
+                    </li>
+                  ))}
+                </ul>
+                {editingPrompt ? (
+                  <div className="prompt-editor">
+                    {([
+                      ["id", "Id"],
+                      ["name", "Name"],
+                      ["icon", "Icon"],
+                      ["description", "Description"],
+                      ["category", "Category (optional)"],
+                    ] as const).map(([field, label]) => (
+                      <input
+                        key={field}
+                        type="text"
+                        className="api-key-input"
+                        placeholder={label}
+                        value={editingPrompt.prompt[field] ?? ""}
+                        onChange={(e) => setEditingPrompt({
+                          ...editingPrompt,
+                          prompt: { ...editingPrompt.prompt, [field]: field === "category" ? e.target.value || undefined : e.target.value },
+                        })}
+                      />
+                    ))}
+                    <div className="prompt-editor-actions">
+                      {(["pinned", "hidden"] as const).map((flag) => (
+                        <label key={flag}>
+                          <input
+                            type="checkbox"
+                            checked={editingPrompt.prompt[flag] ?? false}
+                            onChange={(e) => setEditingPrompt({
+                              ...editingPrompt,
+                              prompt: { ...editingPrompt.prompt, [flag]: e.target.checked },
+                            })}
+                          />
+                          {flag === "pinned" ? "Pinned" : "Hidden"}
+                        </label>
+                      ))}
+                    </div>
+                    {editingPrompt.prompt.steps?.length ? (
+                      <p className="setting-hint">
+                        Pipeline: runs {editingPrompt.prompt.steps.join(" → ")}. Edit its steps in prompts.yaml.
+                      </p>
+                    ) : (
+                      <textarea
+                        placeholder="System prompt: what the AI should do with the text"
+                        value={editingPrompt.prompt.system_prompt}
+                        onChange={(e) => setEditingPrompt({
+                          ...editingPrompt,
+                          prompt: { ...editingPrompt.prompt, system_prompt: e.target.value },
+                        })}
+                      />
+                    )}
+                    <div className="prompt-editor-actions">
+                      <button className="btn-primary" onClick={saveEditedPrompt}>Save</button>
+                      <button onClick={() => setEditingPrompt(null)}>Cancel</button>
+                    </div>
+                  </div>
+                ) : (
+                  <button
+                    onClick={() => setEditingPrompt({
+                      originalId: null,
+                      prompt: { id: "", name: "", description: "", system_prompt: "", icon: "✨" },
+                    })}
+                  >
+                    New Prompt
+                  </button>
+                )}
+                <p className="setting-hint">
+                  Saved to prompts.yaml; the previous version is kept as prompts.yaml.bak. Saving rewrites the file, so comments and unknown keys in it are not kept.
+                </p>
+              </div>
+
               <div className="setting-group">
                 <label>
                   <strong>Backend Status</strong>
                 </label>
                 <ul className="auth-status">
-                  <li>
-                    Claude CLI: {claudeCliAvailable ?
-                      <span className="status-ok">✓ Installed</span> :
-                      <span className="status-warn">⚠ Not found</span>
-                    }
-                  </li>
-                  <li>
-                    Codex CLI: {codexCliAvailable ?
-                      <span className="status-ok">✓ Installed</span> :
-                      <span className="status-warn">⚠ Not found</span>
-                    }
-                  </li>
+                  {backends.map((backend) => (
+                    <li key={backend.id}>
+                      {backend.name}: {backend.available ?
+                        <span className="status-ok">✓ Ready</span> :
+                        <span className="status-warn">⚠ Not set up</span>
+                      }
+                    </li>
+                  ))}
                 </ul>
                 <p className="setting-hint">
                   Install Claude with <code>brew install claude</code>, Codex with <code>npm install -g @openai/codex</code>.